@@ -0,0 +1,413 @@
+//! Compact recording format.
+//!
+//! Controller sticks only report whole steps of 1/127, so inputs are stored as a byte per axis,
+//! and only for the axes that changed since the previous input. Waits are stored as varints, and
+//! inputs that repeat the previous one are dropped entirely. Everything else is stored as in the
+//! regular format.
+//!
+//! Inputs that aren't exact multiples of 1/127 are stored as raw floats, so decoding gives back
+//! exactly [`normalize`] of what was encoded: the same events with repeated inputs dropped and
+//! back-to-back waits merged, which replays identically.
+
+use alloc::vec::Vec;
+
+use crate::{
+    Event, STICK_STEPS,
+    format::{self, DecodeError, EventReader, Read},
+};
+
+pub const VERSION: u16 = 1;
+
+// 0x01..=0x07 are quantized inputs, with the low three bits marking which axes follow.
+const OP_INPUT_DELTA_MAX: u8 = 0x07;
+const OP_INPUT_RAW: u8 = 0x08;
+const OP_WAIT: u8 = 0x09;
+const OP_EVENT: u8 = 0x0A;
+
+/// Drops inputs that repeat the previous input, merges back-to-back waits and removes empty
+/// ones. Merged waits too long for a `u64` stop at `u64::MAX`.
+pub fn normalize(events: &[Event]) -> Vec<Event> {
+    let mut normalized: Vec<Event> = Vec::with_capacity(events.len());
+    let mut input = None;
+
+    for &event in events {
+        match event {
+            Event::Input { x, y, r } => {
+                let bits = [x.to_bits(), y.to_bits(), r.to_bits()];
+
+                if input == Some(bits) {
+                    continue;
+                }
+
+                input = Some(bits);
+                normalized.push(event);
+            }
+            Event::Wait(0) => {}
+            Event::Wait(micros) => match normalized.last_mut() {
+                Some(Event::Wait(previous)) => *previous = previous.saturating_add(micros),
+                _ => normalized.push(event),
+            },
+            event => normalized.push(event),
+        }
+    }
+
+    normalized
+}
+
+/// The stick step `value` is exactly on, if any.
+fn quantize(value: f64) -> Option<i8> {
+    let scaled = value * STICK_STEPS;
+    let step = if scaled >= 0. {
+        (scaled + 0.5) as i32
+    } else {
+        (scaled - 0.5) as i32
+    };
+
+    let exact = (step as f64 / STICK_STEPS).to_bits() == value.to_bits();
+    (exact && step.abs() <= 127).then_some(step as i8)
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+pub fn encode(events: &[Event]) -> Vec<u8> {
+    let events = normalize(events);
+    let mut payload = Vec::new();
+    let mut previous: Option<[i8; 3]> = None;
+
+    for event in &events {
+        match *event {
+            Event::Input { x, y, r } => {
+                let steps = [quantize(x), quantize(y), quantize(r)];
+
+                if let [Some(x), Some(y), Some(r)] = steps {
+                    let steps = [x, y, r];
+                    let mask = (0..3)
+                        .filter(|&axis| previous.is_none_or(|p| p[axis] != steps[axis]))
+                        .fold(0, |mask, axis| mask | 1 << axis);
+
+                    payload.push(mask);
+                    for (axis, &step) in steps.iter().enumerate() {
+                        if mask & 1 << axis != 0 {
+                            payload.push(step as u8);
+                        }
+                    }
+
+                    previous = Some(steps);
+                } else {
+                    payload.push(OP_INPUT_RAW);
+                    payload.extend_from_slice(&x.to_le_bytes());
+                    payload.extend_from_slice(&y.to_le_bytes());
+                    payload.extend_from_slice(&r.to_le_bytes());
+
+                    // The next quantized input has to restate every axis.
+                    previous = None;
+                }
+            }
+            Event::Wait(micros) => {
+                payload.push(OP_WAIT);
+                write_varint(micros, &mut payload);
+            }
+            event => {
+                payload.push(OP_EVENT);
+                format::encode_event(&event, &mut payload);
+            }
+        }
+    }
+
+    format::with_header(format::COMPACT_MAGIC, VERSION, events.len(), &payload)
+}
+
+#[derive(Default)]
+pub(crate) struct Decoder {
+    previous: Option<[i8; 3]>,
+}
+
+impl Decoder {
+    pub(crate) fn event<R: Read>(
+        &mut self,
+        reader: &mut EventReader<R>,
+    ) -> Result<Event, DecodeError<R::Error>> {
+        let [op] = reader.bytes()?;
+
+        Ok(match op {
+            1..=OP_INPUT_DELTA_MAX => {
+                // The first quantized input after a raw one has to carry every axis.
+                let mut steps = match self.previous {
+                    Some(previous) => previous,
+                    None if op == OP_INPUT_DELTA_MAX => [0; 3],
+                    None => return Err(DecodeError::UnknownEvent(op)),
+                };
+
+                for (axis, step) in steps.iter_mut().enumerate() {
+                    if op & 1 << axis != 0 {
+                        let [byte] = reader.bytes()?;
+                        *step = byte as i8;
+                    }
+                }
+
+                self.previous = Some(steps);
+
+                Event::Input {
+                    x: steps[0] as f64 / STICK_STEPS,
+                    y: steps[1] as f64 / STICK_STEPS,
+                    r: steps[2] as f64 / STICK_STEPS,
+                }
+            }
+            OP_INPUT_RAW => {
+                self.previous = None;
+
+                Event::Input {
+                    x: reader.f64()?,
+                    y: reader.f64()?,
+                    r: reader.f64()?,
+                }
+            }
+            OP_WAIT => {
+                let mut micros = 0u64;
+
+                for shift in (0..64).step_by(7) {
+                    let [byte] = reader.bytes()?;
+                    micros |= ((byte & 0x7F) as u64) << shift;
+
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+
+                Event::Wait(micros)
+            }
+            OP_EVENT => reader.event()?,
+            op => return Err(DecodeError::UnknownEvent(op)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+    use crate::{Mechanism, edit};
+
+    fn input(x: f64, y: f64, r: f64) -> Event {
+        Event::Input { x, y, r }
+    }
+
+    /// One event in the regular format, so floats compare bit for bit, NaN and -0.0 included.
+    fn bytes(event: &Event) -> Vec<u8> {
+        let mut out = Vec::new();
+        format::encode_event(event, &mut out);
+        out
+    }
+
+    /// What a recording commands and when, with inputs that repeat the one before dropped since
+    /// they change nothing.
+    fn commands(events: &[Event]) -> (Vec<(u64, Vec<u8>)>, u64) {
+        let (timeline, end) = edit::timeline(events);
+        let mut previous_input = None;
+        let mut commands = Vec::new();
+
+        for timed in &timeline {
+            let event = bytes(&timed.event);
+
+            if matches!(timed.event, Event::Input { .. }) {
+                if previous_input.as_ref() == Some(&event) {
+                    continue;
+                }
+                previous_input = Some(event.clone());
+            }
+
+            commands.push((timed.time, event));
+        }
+
+        (commands, end)
+    }
+
+    /// Decoding the compact encoding gives exactly `normalize(events)`, which replays the same
+    /// commands at the same times as `events`.
+    fn assert_round_trips(events: &[Event]) {
+        let decoded = format::decode(encode(events).as_slice()).unwrap();
+
+        assert_eq!(format::encode(&decoded), format::encode(&normalize(events)));
+        assert_eq!(commands(&decoded), commands(events));
+    }
+
+    #[test]
+    fn stick_steps() {
+        let mut events = Vec::new();
+
+        for step in (-127..=127).step_by(5) {
+            let step = step as f64;
+            events.push(input(step / STICK_STEPS, -step / STICK_STEPS, 0.));
+            events.push(Event::Wait(28_000));
+            events.push(input(step / STICK_STEPS, 1., 64. / STICK_STEPS));
+            events.push(Event::Wait(27_979));
+        }
+        events.push(input(-1., -1., -1.));
+
+        assert_round_trips(&events);
+
+        // Every input is on the grid, so none need raw floats.
+        assert!(encode(&events).len() * 4 < format::encode(&events).len());
+    }
+
+    #[test]
+    fn off_grid_inputs_fall_back_to_raw() {
+        assert_round_trips(&[
+            input(0.5, 0.1, 1. / 3.),
+            Event::Wait(10),
+            input(64. / STICK_STEPS, 0.1, 0.),
+            Event::Wait(10),
+            // Quantized inputs after a raw one have to restate every axis.
+            input(64. / STICK_STEPS, 0., 0.),
+            Event::Wait(10),
+            input(64. / STICK_STEPS, 1., 0.),
+            Event::Wait(10),
+            input(1.5, -2., 128. / STICK_STEPS),
+            input(0., 0., 0.),
+        ]);
+    }
+
+    #[test]
+    fn negative_zero() {
+        let events = [
+            input(-0., 0., -0.),
+            Event::Wait(10),
+            input(0., 0., 0.),
+            Event::Wait(10),
+            input(-0., -0., -0.),
+        ];
+
+        assert_round_trips(&events);
+
+        let decoded = format::decode(encode(&events).as_slice()).unwrap();
+        assert!(
+            matches!(decoded[0], Event::Input { x, r, .. } if x.is_sign_negative() && r.is_sign_negative())
+        );
+        assert!(matches!(decoded[2], Event::Input { x, .. } if x.is_sign_positive()));
+    }
+
+    #[test]
+    fn nan() {
+        let events = [
+            input(f64::NAN, 0., 0.),
+            Event::Wait(10),
+            input(f64::NAN, 0., 0.),
+            Event::Wait(10),
+            input(0., f64::NAN, 1.),
+            Event::Pose {
+                x: f64::NAN,
+                y: 0.,
+                h: 0.,
+            },
+            Event::Battery(f64::NAN),
+        ];
+
+        assert_round_trips(&events);
+
+        // The repeated NaN input is dropped like any other repeat.
+        assert_eq!(normalize(&events).len(), events.len() - 2);
+    }
+
+    #[test]
+    fn repeated_inputs_are_dropped() {
+        let stop = input(0., 0., 0.);
+        let events = [
+            stop,
+            Event::Wait(10),
+            stop,
+            Event::Wait(20),
+            stop,
+            Event::IntakeFwd,
+            stop,
+            Event::Wait(30),
+        ];
+
+        assert_round_trips(&events);
+        assert_eq!(
+            format::encode(&normalize(&events)),
+            format::encode(&[stop, Event::Wait(30), Event::IntakeFwd, Event::Wait(30)])
+        );
+    }
+
+    #[test]
+    fn waits_merge_and_empty_waits_drop() {
+        let events = [
+            Event::Wait(0),
+            Event::Wait(5),
+            Event::Wait(7),
+            input(1., 0., 0.),
+            Event::Wait(0),
+            input(0., 0., 0.),
+            Event::Wait(1),
+            Event::Wait(0),
+            Event::Wait(2),
+        ];
+
+        assert_round_trips(&events);
+        assert_eq!(
+            format::encode(&normalize(&events)),
+            format::encode(&[
+                Event::Wait(12),
+                input(1., 0., 0.),
+                input(0., 0., 0.),
+                Event::Wait(3),
+            ])
+        );
+    }
+
+    #[test]
+    fn long_waits() {
+        assert_round_trips(&[
+            Event::Wait(1 << 35),
+            input(1., 0., 0.),
+            Event::Wait((1 << 35) - 1),
+            input(0., 0., 0.),
+            Event::Wait((1 << 49) + 3),
+        ]);
+        assert_round_trips(&[Event::Wait(u64::MAX)]);
+    }
+
+    #[test]
+    fn merged_waits_saturate() {
+        assert_eq!(
+            format::encode(&normalize(&[Event::Wait(u64::MAX), Event::Wait(1)])),
+            format::encode(&[Event::Wait(u64::MAX)])
+        );
+    }
+
+    #[test]
+    fn every_event() {
+        let events = vec![
+            Event::Battery(12.6),
+            Event::Pose {
+                x: -1.2,
+                y: 0.6,
+                h: 1.5,
+            },
+            input(0., 1., 0.),
+            Event::IntakeFwd,
+            Event::IntakeRev,
+            Event::IntakeDisable,
+            Event::Wait(1_000),
+            input(0., 1., 0.5),
+            Event::RouterFwd,
+            Event::RouterRev,
+            Event::RouterDisable,
+            input(0., 1., 0.5),
+            Event::SetVoltage(Mechanism::Intake, 6.),
+            Event::SetVoltage(Mechanism::Router, -12.),
+            Event::Wait(1_000),
+            input(0., 0., 0.),
+        ];
+
+        assert_round_trips(&events);
+    }
+}
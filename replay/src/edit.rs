@@ -0,0 +1,473 @@
+//! Editing recordings on a timeline.
+//!
+//! Recordings store relative `Wait`s, which makes cutting them up awkward. These helpers convert
+//! to a timeline of events stamped with their absolute time in microseconds, edit that, and
+//! convert back.
+
+use alloc::vec::Vec;
+
+use crate::{Event, Mechanism};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timed {
+    pub time: u64,
+    pub event: Event,
+}
+
+/// Stamps every non-wait event with its time from the start of the recording. Returns the
+/// timeline and the recording's total duration, which includes any trailing wait.
+pub fn timeline(events: &[Event]) -> (Vec<Timed>, u64) {
+    let mut time = 0;
+    let mut timed = Vec::new();
+
+    for &event in events {
+        match event {
+            Event::Wait(micros) => time += micros,
+            event => timed.push(Timed { time, event }),
+        }
+    }
+
+    (timed, time)
+}
+
+/// Converts a timeline back into events, inserting waits between events and up to `end`.
+pub fn from_timeline(timeline: &[Timed], end: u64) -> Vec<Event> {
+    let mut time = 0;
+    let mut events = Vec::new();
+
+    for timed in timeline {
+        if timed.time > time {
+            events.push(Event::Wait(timed.time - time));
+            time = timed.time;
+        }
+
+        events.push(timed.event);
+    }
+
+    if end > time {
+        events.push(Event::Wait(end - time));
+    }
+
+    events
+}
+
+/// What the robot is doing, tracked so dead time can be found.
+#[derive(Default)]
+struct Activity {
+    drive: bool,
+    intake: bool,
+    router: bool,
+}
+
+impl Activity {
+    fn apply(&mut self, event: &Event) {
+        match *event {
+            Event::Input { x, y, r } => self.drive = x != 0. || y != 0. || r != 0.,
+            Event::IntakeFwd | Event::IntakeRev => self.intake = true,
+            Event::IntakeDisable => self.intake = false,
+            Event::RouterFwd | Event::RouterRev => self.router = true,
+            Event::RouterDisable => self.router = false,
+            Event::SetVoltage(Mechanism::Intake, volts) => self.intake = volts != 0.,
+            Event::SetVoltage(Mechanism::Router, volts) => self.router = volts != 0.,
+            Event::Wait(_) | Event::Pose { .. } | Event::Battery(_) => {}
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        !self.drive && !self.intake && !self.router
+    }
+}
+
+/// Removes the dead time before the robot first does something and after it last comes to a
+/// stop.
+pub fn trim(events: &[Event]) -> Vec<Event> {
+    let (timeline, _) = timeline(events);
+
+    let mut activity = Activity::default();
+    let mut start = None;
+    let mut stop = None;
+
+    for timed in &timeline {
+        let was_idle = activity.is_idle();
+        activity.apply(&timed.event);
+
+        match (was_idle, activity.is_idle()) {
+            (true, false) => {
+                start.get_or_insert(timed.time);
+                stop = None;
+            }
+            (false, true) => stop = Some(timed.time),
+            _ => {}
+        }
+    }
+
+    let Some(start) = start else {
+        return Vec::new();
+    };
+    let stop = stop.unwrap_or_else(|| timeline.last().map_or(start, |timed| timed.time));
+
+    let trimmed: Vec<Timed> = timeline
+        .iter()
+        .filter(|timed| timed.time <= stop)
+        .map(|timed| Timed {
+            time: timed.time.saturating_sub(start),
+            event: timed.event,
+        })
+        .collect();
+
+    from_timeline(&trimmed, stop - start)
+}
+
+/// The part of a recording between `from` and `to`, starting at time zero. The state the robot
+/// was left in before `from` carries over, so the segment replays the same on its own. A `to`
+/// past the end of the recording stops at the end.
+pub fn slice(events: &[Event], from: u64, to: u64) -> Vec<Event> {
+    let (timeline, duration) = timeline(events);
+    let to = to.min(duration);
+
+    let mut input = None;
+    let mut pose = None;
+    let mut battery = None;
+    let mut intake = None;
+    let mut router = None;
+    let mut voltages = Vec::new();
+
+    for timed in timeline.iter().take_while(|timed| timed.time < from) {
+        match timed.event {
+            event @ Event::Input { .. } => input = Some(event),
+            event @ Event::Pose { .. } => pose = Some(event),
+            event @ Event::Battery(_) => battery = Some(event),
+            event @ (Event::IntakeFwd | Event::IntakeRev | Event::IntakeDisable) => {
+                intake = Some(event)
+            }
+            event @ (Event::RouterFwd | Event::RouterRev | Event::RouterDisable) => {
+                router = Some(event)
+            }
+            event @ Event::SetVoltage(mechanism, _) => {
+                voltages.retain(|v| !matches!(v, Event::SetVoltage(m, _) if *m == mechanism));
+                voltages.push(event);
+            }
+            Event::Wait(_) => {}
+        }
+    }
+
+    let mut segment: Vec<Timed> = [battery, pose, input, intake, router]
+        .into_iter()
+        .flatten()
+        .chain(voltages)
+        .map(|event| Timed { time: 0, event })
+        .collect();
+
+    segment.extend(
+        timeline
+            .iter()
+            .filter(|timed| (from..to).contains(&timed.time))
+            .map(|timed| Timed {
+                time: timed.time - from,
+                event: timed.event,
+            }),
+    );
+
+    from_timeline(&segment, to.saturating_sub(from))
+}
+
+/// Plays `b` after `a` finishes.
+pub fn concat(a: &[Event], b: &[Event]) -> Vec<Event> {
+    let mut events = Vec::with_capacity(a.len() + b.len());
+    events.extend_from_slice(a);
+    events.extend_from_slice(b);
+    events
+}
+
+/// Stretches the events between `from` and `to` by `factor`, shifting everything after them.
+/// A factor above 1 slows the segment down.
+pub fn scale(events: &[Event], from: u64, to: u64, factor: f64) -> Vec<Event> {
+    let (timeline, end) = timeline(events);
+
+    let map = |time: u64| {
+        if time <= from {
+            time
+        } else if time <= to {
+            from + ((time - from) as f64 * factor) as u64
+        } else {
+            from + ((to - from) as f64 * factor) as u64 + (time - to)
+        }
+    };
+
+    let scaled: Vec<Timed> = timeline
+        .iter()
+        .map(|timed| Timed {
+            time: map(timed.time),
+            event: timed.event,
+        })
+        .collect();
+
+    from_timeline(&scaled, map(end))
+}
+
+/// Adds `event` at `time`, after any events already at that time.
+pub fn insert(events: &[Event], time: u64, event: Event) -> Vec<Event> {
+    let (mut timeline, end) = timeline(events);
+
+    let index = timeline.partition_point(|timed| timed.time <= time);
+    timeline.insert(index, Timed { time, event });
+
+    from_timeline(&timeline, end.max(time))
+}
+
+/// Plays the drive back to front with negated inputs, so the robot retraces its path back to
+/// where the recording started. Mechanism events are dropped, and the robot ends stopped.
+pub fn reverse(events: &[Event]) -> Vec<Event> {
+    let (timeline, end) = timeline(events);
+    let mut reversed = Vec::new();
+
+    if let Some(battery) = timeline
+        .iter()
+        .find(|timed| matches!(timed.event, Event::Battery(_)))
+    {
+        reversed.push(Timed {
+            time: 0,
+            event: battery.event,
+        });
+    }
+
+    reversed.extend(
+        timeline
+            .iter()
+            .filter(|timed| matches!(timed.event, Event::Pose { .. }))
+            .map(|timed| Timed {
+                time: end - timed.time,
+                event: timed.event,
+            }),
+    );
+
+    // An input is held until the next one, so reversed it starts where the next one was.
+    let inputs: Vec<(u64, f64, f64, f64)> = timeline
+        .iter()
+        .filter_map(|timed| match timed.event {
+            Event::Input { x, y, r } => Some((timed.time, x, y, r)),
+            _ => None,
+        })
+        .collect();
+
+    // Zero stays positive, so stopped sticks still compact to a single step.
+    let negate = |value: f64| if value == 0. { 0. } else { -value };
+
+    for (i, &(time, x, y, r)) in inputs.iter().enumerate() {
+        let until = inputs.get(i + 1).map_or(end, |next| next.0);
+
+        // An input replaced the moment it was given never drove the robot. Reversed, it would
+        // land on the start of the input before it and cancel it, as a final stop would.
+        if until > time {
+            reversed.push(Timed {
+                time: end - until,
+                event: Event::Input {
+                    x: negate(x),
+                    y: negate(y),
+                    r: negate(r),
+                },
+            });
+        }
+
+        if i == 0 {
+            reversed.push(Timed {
+                time: end - time,
+                event: Event::Input {
+                    x: 0.,
+                    y: 0.,
+                    r: 0.,
+                },
+            });
+        }
+    }
+
+    // Stable, so poses stay ahead of inputs at the same time.
+    reversed.sort_by_key(|timed| timed.time);
+
+    from_timeline(&reversed, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    const STOP: Event = Event::Input {
+        x: 0.,
+        y: 0.,
+        r: 0.,
+    };
+
+    fn input(x: f64, y: f64, r: f64) -> Event {
+        Event::Input { x, y, r }
+    }
+
+    fn pose(x: f64) -> Event {
+        Event::Pose { x, y: 0., h: 0. }
+    }
+
+    #[test]
+    fn trim_cuts_idle_time_at_both_ends() {
+        let events = [
+            Event::Wait(100),
+            pose(0.),
+            Event::Wait(50),
+            input(0., 1., 0.),
+            Event::Wait(200),
+            STOP,
+            Event::Wait(300),
+        ];
+
+        // The pose from before the robot moved is kept, pulled up to the new start.
+        assert_eq!(
+            trim(&events),
+            vec![pose(0.), input(0., 1., 0.), Event::Wait(200), STOP]
+        );
+    }
+
+    #[test]
+    fn trim_keeps_a_recording_that_never_stops() {
+        let events = [
+            Event::Wait(100),
+            Event::IntakeFwd,
+            Event::Wait(100),
+            input(1., 0., 0.),
+        ];
+
+        assert_eq!(
+            trim(&events),
+            vec![Event::IntakeFwd, Event::Wait(100), input(1., 0., 0.)]
+        );
+    }
+
+    #[test]
+    fn trim_empties_an_idle_recording() {
+        assert_eq!(trim(&[pose(0.), Event::Wait(100), STOP]), vec![]);
+    }
+
+    #[test]
+    fn slice_carries_state_over() {
+        let events = [
+            Event::Battery(12.),
+            Event::IntakeFwd,
+            Event::SetVoltage(Mechanism::Router, 6.),
+            input(1., 0., 0.),
+            Event::Wait(100),
+            pose(1.),
+            Event::SetVoltage(Mechanism::Router, 3.),
+            Event::Wait(100),
+            STOP,
+            Event::Wait(100),
+        ];
+
+        assert_eq!(
+            slice(&events, 150, 250),
+            vec![
+                Event::Battery(12.),
+                pose(1.),
+                input(1., 0., 0.),
+                Event::IntakeFwd,
+                Event::SetVoltage(Mechanism::Router, 3.),
+                Event::Wait(50),
+                STOP,
+                Event::Wait(50),
+            ]
+        );
+    }
+
+    #[test]
+    fn slice_stops_at_the_end() {
+        let events = [input(1., 0., 0.), Event::Wait(100), STOP, Event::Wait(50)];
+
+        assert_eq!(slice(&events, 0, u64::MAX), events);
+        assert_eq!(slice(&events, 120, u64::MAX), vec![STOP, Event::Wait(30)]);
+    }
+
+    #[test]
+    fn scale_stretches_only_the_segment() {
+        let events = [
+            input(1., 0., 0.),
+            Event::Wait(100),
+            input(0., 1., 0.),
+            Event::Wait(100),
+            input(0., 0., 1.),
+            Event::Wait(100),
+        ];
+
+        assert_eq!(
+            scale(&events, 100, 200, 2.),
+            vec![
+                input(1., 0., 0.),
+                Event::Wait(100),
+                input(0., 1., 0.),
+                Event::Wait(200),
+                input(0., 0., 1.),
+                Event::Wait(100),
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_goes_after_events_at_the_same_time() {
+        let events = [input(1., 0., 0.), Event::Wait(100), STOP, Event::Wait(100)];
+
+        assert_eq!(
+            insert(&events, 100, Event::IntakeFwd),
+            vec![
+                input(1., 0., 0.),
+                Event::Wait(100),
+                STOP,
+                Event::IntakeFwd,
+                Event::Wait(100),
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_past_the_end_extends_the_recording() {
+        let events = [input(1., 0., 0.), Event::Wait(100), STOP];
+
+        assert_eq!(
+            insert(&events, 250, Event::IntakeDisable),
+            vec![
+                input(1., 0., 0.),
+                Event::Wait(100),
+                STOP,
+                Event::Wait(150),
+                Event::IntakeDisable,
+            ]
+        );
+    }
+
+    #[test]
+    fn reverse_retraces_each_input_for_as_long_as_it_was_held() {
+        let events = [
+            Event::Battery(12.),
+            pose(0.),
+            input(0., 1., 0.),
+            Event::IntakeFwd,
+            Event::Wait(100),
+            pose(1.),
+            input(1., 0., 0.),
+            Event::Wait(300),
+            STOP,
+            Event::IntakeDisable,
+        ];
+
+        // The trailing stop was held for no time, so it doesn't cancel the first reversed input.
+        assert_eq!(
+            reverse(&events),
+            vec![
+                Event::Battery(12.),
+                input(-1., 0., 0.),
+                Event::Wait(300),
+                pose(1.),
+                input(0., -1., 0.),
+                Event::Wait(100),
+                pose(0.),
+                STOP,
+            ]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use core::f64::consts::{PI, TAU};
+
+/// Stick resolution of the V5 controller. Inputs are whole steps of `1 / STICK_STEPS`.
+pub const STICK_STEPS: f64 = 127.;
+
+/// Smallest change in battery voltage worth recording, in volts.
+pub const BATTERY_RESOLUTION: f64 = 0.05;
+
+/// Wraps an angle in radians, such as the difference between two headings, to [-PI, PI].
+pub fn wrap(angle: f64) -> f64 {
+    let angle = angle % TAU;
+
+    if angle > PI {
+        angle - TAU
+    } else if angle < -PI {
+        angle + TAU
+    } else {
+        angle
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mechanism {
+    Intake,
+    Router,
+}
+
+impl Mechanism {
+    pub const ALL: [Self; 2] = [Self::Intake, Self::Router];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Input {
+        x: f64,
+        y: f64,
+        r: f64,
+    },
+    Wait(u64),
+    /// GPS pose the robot was at, in meters and radians.
+    Pose {
+        x: f64,
+        y: f64,
+        h: f64,
+    },
+    /// Battery voltage while recording, used to compensate replay on a different battery.
+    Battery(f64),
+    IntakeFwd,
+    IntakeRev,
+    IntakeDisable,
+    RouterFwd,
+    RouterRev,
+    RouterDisable,
+    SetVoltage(Mechanism, f64),
+}
@@ -0,0 +1,253 @@
+//! Recording file format
+//!
+//! Layout (all integers little-endian):
+//!
+//! | offset | size | field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 4    | magic, `b"PBRC"`                        |
+//! | 4      | 2    | format version                          |
+//! | 6      | 4    | event count                             |
+//! | 10     | 4    | CRC-32 of the event payload             |
+//! | 14     | ..   | events, each a tag byte and its payload |
+//!
+//! Version 2 added the router and raw voltage events, version 3 added poses, and version 4 added
+//! battery voltage. Older files are still readable since their tags are a subset.
+//!
+//! Compact recordings (see [`crate::compact`]) share the header, with their own magic and
+//! version, and are read by the same [`decode`].
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use crate::{Event, Mechanism, compact};
+
+pub const MAGIC: [u8; 4] = *b"PBRC";
+pub const COMPACT_MAGIC: [u8; 4] = *b"PBRZ";
+pub const FORMAT_VERSION: u16 = 4;
+pub const HEADER_LEN: usize = 14;
+
+const TAG_INPUT: u8 = 0;
+const TAG_WAIT: u8 = 1;
+const TAG_INTAKE_FWD: u8 = 2;
+const TAG_INTAKE_REV: u8 = 3;
+const TAG_INTAKE_DISABLE: u8 = 4;
+const TAG_ROUTER_FWD: u8 = 5;
+const TAG_ROUTER_REV: u8 = 6;
+const TAG_ROUTER_DISABLE: u8 = 7;
+const TAG_SET_VOLTAGE: u8 = 8;
+const TAG_POSE: u8 = 9;
+const TAG_BATTERY: u8 = 10;
+
+/// Source of bytes for the streaming decoder, so the robot can read straight from the SD card.
+pub trait Read {
+    type Error;
+
+    /// Reads into `buf`, returning the number of bytes read, or 0 at the end of the input.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+impl Read for &[u8] {
+    type Error = Infallible;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.len());
+        let (head, tail) = self.split_at(len);
+
+        buf[..len].copy_from_slice(head);
+        *self = tail;
+
+        Ok(len)
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError<E> {
+    Read(E),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    UnknownEvent(u8),
+    UnknownMechanism(u8),
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+fn read_exact<R: Read>(reader: &mut R, mut buf: &mut [u8]) -> Result<(), DecodeError<R::Error>> {
+    while !buf.is_empty() {
+        match reader.read(buf).map_err(DecodeError::Read)? {
+            0 => return Err(DecodeError::Truncated),
+            n => buf = &mut core::mem::take(&mut buf)[n..],
+        }
+    }
+
+    Ok(())
+}
+
+fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    crc
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes)
+}
+
+pub(crate) fn encode_event(event: &Event, out: &mut Vec<u8>) {
+    match *event {
+        Event::Input { x, y, r } => {
+            out.push(TAG_INPUT);
+            out.extend_from_slice(&x.to_le_bytes());
+            out.extend_from_slice(&y.to_le_bytes());
+            out.extend_from_slice(&r.to_le_bytes());
+        }
+        Event::Wait(micros) => {
+            out.push(TAG_WAIT);
+            out.extend_from_slice(&micros.to_le_bytes());
+        }
+        Event::Pose { x, y, h } => {
+            out.push(TAG_POSE);
+            out.extend_from_slice(&x.to_le_bytes());
+            out.extend_from_slice(&y.to_le_bytes());
+            out.extend_from_slice(&h.to_le_bytes());
+        }
+        Event::Battery(volts) => {
+            out.push(TAG_BATTERY);
+            out.extend_from_slice(&volts.to_le_bytes());
+        }
+        Event::IntakeFwd => out.push(TAG_INTAKE_FWD),
+        Event::IntakeRev => out.push(TAG_INTAKE_REV),
+        Event::IntakeDisable => out.push(TAG_INTAKE_DISABLE),
+        Event::RouterFwd => out.push(TAG_ROUTER_FWD),
+        Event::RouterRev => out.push(TAG_ROUTER_REV),
+        Event::RouterDisable => out.push(TAG_ROUTER_DISABLE),
+        Event::SetVoltage(mechanism, volts) => {
+            out.push(TAG_SET_VOLTAGE);
+            out.push(mechanism.id());
+            out.extend_from_slice(&volts.to_le_bytes());
+        }
+    }
+}
+
+pub(crate) fn with_header(magic: [u8; 4], version: u16, len: usize, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+
+    out.extend_from_slice(&magic);
+    out.extend_from_slice(&version.to_le_bytes());
+    out.extend_from_slice(&(len as u32).to_le_bytes());
+    out.extend_from_slice(&crc32(payload).to_le_bytes());
+    out.extend_from_slice(payload);
+
+    out
+}
+
+pub fn encode(events: &[Event]) -> Vec<u8> {
+    let mut payload = Vec::new();
+
+    for event in events {
+        encode_event(event, &mut payload);
+    }
+
+    with_header(MAGIC, FORMAT_VERSION, events.len(), &payload)
+}
+
+/// Reads events one at a time from the underlying reader, checksumming the payload as it goes.
+pub(crate) struct EventReader<R: Read> {
+    reader: R,
+    crc: u32,
+}
+
+impl<R: Read> EventReader<R> {
+    pub(crate) fn bytes<const N: usize>(&mut self) -> Result<[u8; N], DecodeError<R::Error>> {
+        let mut buf = [0; N];
+        read_exact(&mut self.reader, &mut buf)?;
+        self.crc = crc32_update(self.crc, &buf);
+
+        Ok(buf)
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64, DecodeError<R::Error>> {
+        Ok(f64::from_le_bytes(self.bytes()?))
+    }
+
+    pub(crate) fn event(&mut self) -> Result<Event, DecodeError<R::Error>> {
+        let [tag] = self.bytes()?;
+
+        Ok(match tag {
+            TAG_INPUT => Event::Input {
+                x: self.f64()?,
+                y: self.f64()?,
+                r: self.f64()?,
+            },
+            TAG_WAIT => Event::Wait(u64::from_le_bytes(self.bytes()?)),
+            TAG_POSE => Event::Pose {
+                x: self.f64()?,
+                y: self.f64()?,
+                h: self.f64()?,
+            },
+            TAG_BATTERY => Event::Battery(self.f64()?),
+            TAG_INTAKE_FWD => Event::IntakeFwd,
+            TAG_INTAKE_REV => Event::IntakeRev,
+            TAG_INTAKE_DISABLE => Event::IntakeDisable,
+            TAG_ROUTER_FWD => Event::RouterFwd,
+            TAG_ROUTER_REV => Event::RouterRev,
+            TAG_ROUTER_DISABLE => Event::RouterDisable,
+            TAG_SET_VOLTAGE => {
+                let [id] = self.bytes()?;
+                let mechanism = Mechanism::from_id(id).ok_or(DecodeError::UnknownMechanism(id))?;
+
+                Event::SetVoltage(mechanism, self.f64()?)
+            }
+            tag => return Err(DecodeError::UnknownEvent(tag)),
+        })
+    }
+}
+
+pub fn decode<R: Read>(mut reader: R) -> Result<Vec<Event>, DecodeError<R::Error>> {
+    let mut header = [0; HEADER_LEN];
+    read_exact(&mut reader, &mut header)?;
+
+    let magic = [header[0], header[1], header[2], header[3]];
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    let len = u32::from_le_bytes([header[6], header[7], header[8], header[9]]) as usize;
+    let expected = u32::from_le_bytes([header[10], header[11], header[12], header[13]]);
+
+    let compact = match magic {
+        MAGIC if (1..=FORMAT_VERSION).contains(&version) => false,
+        COMPACT_MAGIC if version == compact::VERSION => true,
+        MAGIC | COMPACT_MAGIC => return Err(DecodeError::UnsupportedVersion(version)),
+        _ => return Err(DecodeError::BadMagic),
+    };
+
+    // The count comes from the file, so don't trust it for a huge allocation up front.
+    let mut events = Vec::with_capacity(len.min(4096));
+    let mut reader = EventReader { reader, crc: !0 };
+
+    if compact {
+        let mut decoder = compact::Decoder::default();
+
+        for _ in 0..len {
+            events.push(decoder.event(&mut reader)?);
+        }
+    } else {
+        for _ in 0..len {
+            events.push(reader.event()?);
+        }
+    }
+
+    let actual = !reader.crc;
+    if actual != expected {
+        return Err(DecodeError::ChecksumMismatch { expected, actual });
+    }
+
+    Ok(events)
+}
@@ -0,0 +1,17 @@
+//! Auton replay events and the tooling around them.
+//!
+//! This crate is `no_std` so it can be shared between the robot program and the host-side tools
+//! in `tools/`.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod compact;
+pub mod edit;
+mod event;
+pub mod format;
+pub mod path;
+pub mod validate;
+
+pub use event::{BATTERY_RESOLUTION, Event, Mechanism, STICK_STEPS, wrap};
@@ -0,0 +1,150 @@
+//! Converting recordings into paths for motion control.
+//!
+//! The recorded pose trace is simplified with Ramer-Douglas-Peucker into a handful of waypoints.
+//! Poses that mechanism events happened at are always kept, so the intake and router still
+//! switch at the same spot on the field when the path is followed instead of replayed.
+
+use alloc::vec::Vec;
+
+use crate::Event;
+
+/// Field pose in meters and radians, matching `Event::Pose`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    pub x: f64,
+    pub y: f64,
+    pub h: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    MoveTo(Waypoint),
+    /// Any non-drive event, run once the previous waypoint is reached.
+    Event(Event),
+}
+
+fn is_drive(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Input { .. } | Event::Wait(_) | Event::Pose { .. } | Event::Battery(_)
+    )
+}
+
+/// Squared distance from `p` to the line through `a` and `b`.
+fn line_distance_sq(p: Waypoint, a: Waypoint, b: Waypoint) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let len_sq = dx * dx + dy * dy;
+
+    if len_sq == 0. {
+        let (px, py) = (p.x - a.x, p.y - a.y);
+        return px * px + py * py;
+    }
+
+    let cross = dx * (p.y - a.y) - dy * (p.x - a.x);
+    cross * cross / len_sq
+}
+
+fn simplify_into(trace: &[Waypoint], tolerance_sq: f64, keep: &mut [bool]) {
+    if trace.len() < 3 {
+        return;
+    }
+
+    let (first, last) = (trace[0], trace[trace.len() - 1]);
+    let (index, distance_sq) = trace[1..trace.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| (i + 1, line_distance_sq(p, first, last)))
+        .fold((0, 0.), |max, cur| if cur.1 > max.1 { cur } else { max });
+
+    if distance_sq > tolerance_sq {
+        keep[index] = true;
+        simplify_into(&trace[..=index], tolerance_sq, &mut keep[..=index]);
+        simplify_into(&trace[index..], tolerance_sq, &mut keep[index..]);
+    }
+}
+
+/// Marks which points of `trace` survive simplification. Points already marked in `keep` are
+/// treated as fixed, and the trace is simplified independently between them.
+pub fn simplify(trace: &[Waypoint], tolerance: f64, keep: &mut [bool]) {
+    if trace.is_empty() {
+        return;
+    }
+
+    keep[0] = true;
+    keep[trace.len() - 1] = true;
+
+    let mut start = 0;
+    for end in 1..trace.len() {
+        if keep[end] {
+            simplify_into(
+                &trace[start..=end],
+                tolerance * tolerance,
+                &mut keep[start..=end],
+            );
+            start = end;
+        }
+    }
+}
+
+/// Every recorded pose, in order.
+pub fn pose_trace(events: &[Event]) -> Vec<Waypoint> {
+    events
+        .iter()
+        .filter_map(|event| match *event {
+            Event::Pose { x, y, h } => Some(Waypoint { x, y, h }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Converts a recording into waypoints and mechanism events. `tolerance` is the furthest, in
+/// meters, the simplified path may stray from the recorded one.
+pub fn to_path(events: &[Event], tolerance: f64) -> Vec<Step> {
+    let trace = pose_trace(events);
+    let mut keep = alloc::vec![false; trace.len()];
+
+    // Pin the pose each mechanism event happened at.
+    let mut pose = None;
+    for event in events {
+        match event {
+            Event::Pose { .. } => pose = Some(pose.map_or(0, |i| i + 1)),
+            event if !is_drive(event) => {
+                if let Some(i) = pose {
+                    keep[i] = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    simplify(&trace, tolerance, &mut keep);
+
+    let mut steps = Vec::new();
+    let mut pose = 0;
+
+    for event in events {
+        match *event {
+            Event::Pose { .. } => {
+                if keep[pose] {
+                    steps.push(Step::MoveTo(trace[pose]));
+                }
+                pose += 1;
+            }
+            event if !is_drive(&event) => steps.push(Step::Event(event)),
+            _ => {}
+        }
+    }
+
+    steps
+}
+
+/// Turns a path back into a recording, with each waypoint as a pose event.
+pub fn to_events(steps: &[Step]) -> Vec<Event> {
+    steps
+        .iter()
+        .map(|step| match *step {
+            Step::MoveTo(Waypoint { x, y, h }) => Event::Pose { x, y, h },
+            Step::Event(event) => event,
+        })
+        .collect()
+}
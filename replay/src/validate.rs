@@ -0,0 +1,232 @@
+//! Checking recordings before they are run.
+
+use alloc::vec::Vec;
+
+use crate::{Event, Mechanism, edit};
+
+/// Length of the autonomous period in a match, in microseconds.
+pub const AUTONOMOUS_PERIOD: u64 = 15_000_000;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Forward,
+    Reverse,
+    #[default]
+    Disabled,
+    Voltage(f64),
+}
+
+impl Command {
+    pub fn is_disabled(&self) -> bool {
+        matches!(self, Self::Disabled | Self::Voltage(0.))
+    }
+}
+
+/// What the robot is left commanded to do once the recording finishes.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct FinalState {
+    pub input: (f64, f64, f64),
+    pub intake: Command,
+    pub router: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Issue {
+    /// A stick input outside of [-1, 1].
+    OutOfRange {
+        time: u64,
+        event: Event,
+    },
+    /// Events that would run after the autonomous period ends, starting at `time`.
+    AfterPeriod {
+        time: u64,
+        count: usize,
+    },
+    DriveNotStopped,
+    IntakeNotDisabled,
+    RouterNotDisabled,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// Sum of every wait, in microseconds.
+    pub duration: u64,
+    pub final_state: FinalState,
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks that a recording fits within `period` microseconds and leaves the robot stopped.
+pub fn validate(events: &[Event], period: u64) -> Report {
+    let (timeline, duration) = edit::timeline(events);
+    let mut state = FinalState::default();
+    let mut issues = Vec::new();
+
+    for timed in &timeline {
+        let (time, event) = (timed.time, timed.event);
+
+        match event {
+            Event::Input { x, y, r } => {
+                if [x, y, r].iter().any(|value| !(-1.0..=1.0).contains(value)) {
+                    issues.push(Issue::OutOfRange { time, event });
+                }
+
+                state.input = (x, y, r);
+            }
+            Event::IntakeFwd => state.intake = Command::Forward,
+            Event::IntakeRev => state.intake = Command::Reverse,
+            Event::IntakeDisable => state.intake = Command::Disabled,
+            Event::RouterFwd => state.router = Command::Forward,
+            Event::RouterRev => state.router = Command::Reverse,
+            Event::RouterDisable => state.router = Command::Disabled,
+            Event::SetVoltage(Mechanism::Intake, volts) => state.intake = Command::Voltage(volts),
+            Event::SetVoltage(Mechanism::Router, volts) => state.router = Command::Voltage(volts),
+            Event::Wait(_) | Event::Pose { .. } | Event::Battery(_) => {}
+        }
+    }
+
+    let late = timeline.iter().filter(|timed| timed.time > period);
+    if let Some(first) = late.clone().next() {
+        issues.push(Issue::AfterPeriod {
+            time: first.time,
+            count: late.count(),
+        });
+    }
+
+    if state.input != (0., 0., 0.) {
+        issues.push(Issue::DriveNotStopped);
+    }
+
+    if !state.intake.is_disabled() {
+        issues.push(Issue::IntakeNotDisabled);
+    }
+
+    if !state.router.is_disabled() {
+        issues.push(Issue::RouterNotDisabled);
+    }
+
+    Report {
+        duration,
+        final_state: state,
+        issues,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    const STOP: Event = Event::Input {
+        x: 0.,
+        y: 0.,
+        r: 0.,
+    };
+
+    #[test]
+    fn stopped_recording_passes() {
+        let events = [
+            Event::IntakeFwd,
+            Event::Input {
+                x: 0.,
+                y: 1.,
+                r: 0.,
+            },
+            Event::Wait(1_000_000),
+            STOP,
+            Event::IntakeDisable,
+            Event::SetVoltage(Mechanism::Router, 0.),
+        ];
+
+        let report = validate(&events, AUTONOMOUS_PERIOD);
+
+        assert!(report.is_ok(), "{:?}", report.issues);
+        assert_eq!(report.duration, 1_000_000);
+    }
+
+    #[test]
+    fn out_of_range_input() {
+        let input = Event::Input {
+            x: 0.,
+            y: 1.5,
+            r: 0.,
+        };
+        let events = [Event::Wait(10), input, Event::Wait(10), STOP];
+
+        assert_eq!(
+            validate(&events, AUTONOMOUS_PERIOD).issues,
+            vec![Issue::OutOfRange {
+                time: 10,
+                event: input
+            }]
+        );
+    }
+
+    #[test]
+    fn nan_input_is_out_of_range() {
+        let input = Event::Input {
+            x: f64::NAN,
+            y: 0.,
+            r: 0.,
+        };
+        let issues = validate(&[input, STOP], AUTONOMOUS_PERIOD).issues;
+
+        assert!(matches!(issues[..], [Issue::OutOfRange { time: 0, .. }]));
+    }
+
+    #[test]
+    fn events_after_period() {
+        let events = [STOP, Event::Wait(20), STOP, Event::Wait(5), STOP, STOP];
+
+        assert_eq!(
+            validate(&events, 10).issues,
+            vec![Issue::AfterPeriod { time: 20, count: 3 }]
+        );
+    }
+
+    #[test]
+    fn events_at_period_end_are_in_time() {
+        let events = [STOP, Event::Wait(10), STOP];
+
+        assert!(validate(&events, 10).is_ok());
+    }
+
+    #[test]
+    fn drive_not_stopped() {
+        let events = [Event::Input {
+            x: 0.,
+            y: 0.5,
+            r: 0.,
+        }];
+
+        assert_eq!(
+            validate(&events, AUTONOMOUS_PERIOD).issues,
+            vec![Issue::DriveNotStopped]
+        );
+    }
+
+    #[test]
+    fn mechanisms_not_disabled() {
+        let events = [STOP, Event::IntakeRev, Event::RouterFwd];
+
+        assert_eq!(
+            validate(&events, AUTONOMOUS_PERIOD).issues,
+            vec![Issue::IntakeNotDisabled, Issue::RouterNotDisabled]
+        );
+    }
+
+    #[test]
+    fn nonzero_voltage_is_not_disabled() {
+        let events = [STOP, Event::SetVoltage(Mechanism::Intake, 6.)];
+        let report = validate(&events, AUTONOMOUS_PERIOD);
+
+        assert_eq!(report.issues, vec![Issue::IntakeNotDisabled]);
+        assert_eq!(report.final_state.intake, Command::Voltage(6.));
+    }
+}
@@ -1,13 +1,6 @@
 // Auton replay
 
-#[derive(Clone, Copy)]
-pub enum Event {
-    Input { x: f64, y: f64, r: f64 },
-    Wait(u64),
-    IntakeFwd,
-    IntakeRev,
-    IntakeDisable,
-}
+pub use replay::Event;
 
 pub static FRAMES: [Event; 1501] = [
     Event::Input {
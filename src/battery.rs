@@ -0,0 +1,21 @@
+// Battery voltage compensation
+//
+// Replayed stick inputs turn into motor voltages, so a recording drives faster on a fresh battery
+// than on a tired one. Scaling by the ratio of the recorded to the current battery voltage keeps
+// routes consistent across a competition day.
+
+use vexide::devices::battery;
+
+// Limits on the compensation, so a bad reading can't make the robot crawl or lunge.
+const MIN_SCALE: f64 = 0.85;
+const MAX_SCALE: f64 = 1.2;
+
+pub fn compensation(recorded: f64) -> f64 {
+    let current = battery::voltage();
+
+    if recorded <= 0. || current <= 0. {
+        return 1.;
+    }
+
+    (recorded / current).clamp(MIN_SCALE, MAX_SCALE)
+}
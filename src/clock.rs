@@ -0,0 +1,81 @@
+// Replay timing
+//
+// Waits are scheduled against absolute deadlines measured from the start of the route, so time
+// lost to scheduler jitter or slow frames is caught up instead of pushing every later frame back.
+
+use core::time::Duration;
+
+use vexide::{
+    devices::controller::Controller,
+    time::{Instant, sleep},
+};
+
+// Long waits are split up so an abort is noticed as soon as the controller reports it.
+const POLL_INTERVAL: Duration = Controller::UPDATE_INTERVAL;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Lateness {
+    pub max: Duration,
+    pub mean: Duration,
+    pub waits: u32,
+}
+
+pub struct ReplayClock {
+    start: Instant,
+    elapsed: Duration,
+    max_late: Duration,
+    total_late: Duration,
+    waits: u32,
+}
+
+impl ReplayClock {
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Duration::ZERO,
+            max_late: Duration::ZERO,
+            total_late: Duration::ZERO,
+            waits: 0,
+        }
+    }
+
+    pub async fn wait(&mut self, micros: u64) {
+        self.wait_unless(micros, || false).await;
+    }
+
+    /// Waits like [`ReplayClock::wait`], checking `abort` every `POLL_INTERVAL` and giving up
+    /// early if it returns true. Returns whether the wait ran to its deadline.
+    pub async fn wait_unless(&mut self, micros: u64, mut abort: impl FnMut() -> bool) -> bool {
+        self.elapsed += Duration::from_micros(micros);
+        let deadline = self.start + self.elapsed;
+
+        loop {
+            let now = Instant::now();
+            if deadline <= now {
+                break;
+            }
+
+            if abort() {
+                return false;
+            }
+
+            sleep(deadline.duration_since(now).min(POLL_INTERVAL)).await;
+        }
+
+        let late = Instant::now().saturating_duration_since(deadline);
+
+        self.max_late = self.max_late.max(late);
+        self.total_late += late;
+        self.waits += 1;
+
+        true
+    }
+
+    pub fn lateness(&self) -> Lateness {
+        Lateness {
+            max: self.max_late,
+            mean: self.total_late.checked_div(self.waits).unwrap_or_default(),
+            waits: self.waits,
+        }
+    }
+}
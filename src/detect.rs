@@ -0,0 +1,48 @@
+// Alliance and starting side detection
+//
+// The GPS reports absolute field coordinates, so the starting tile can be read off the robot's
+// position instead of trusting the tile picked on the brain screen. Following `mirror`, the
+// alliances split along x with red on -x, and sides are named from the drive team's wall, so Left
+// is +y for red and -y for blue. Robots start facing away from their own alliance wall.
+
+use evian::prelude::*;
+
+use crate::teams::{Alliance, Side};
+
+// Positions closer than this to a dividing line, in meters, could be on either side of it.
+const POSITION_MARGIN: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    pub route: (Alliance, Side),
+    /// Whether the robot is too close to a dividing line, or facing the wrong way, to be sure.
+    pub ambiguous: bool,
+}
+
+pub fn detect(position: Vec2<f64>, heading: Angle) -> Detection {
+    let alliance = if position.x < 0. {
+        Alliance::Red
+    } else {
+        Alliance::Blue
+    };
+    // Red looks down +x, with +y on its left; blue looks down -x, with -y on its left.
+    let left = match alliance {
+        Alliance::Red => position.y > 0.,
+        Alliance::Blue => position.y < 0.,
+    };
+    let side = if left { Side::Left } else { Side::Right };
+
+    // Forward is (sin h, cos h) on the compass, so red faces +x with a positive sine and blue
+    // faces -x with a negative one.
+    let facing_field = match alliance {
+        Alliance::Red => heading.sin() > 0.,
+        Alliance::Blue => heading.sin() < 0.,
+    };
+
+    Detection {
+        route: (alliance, side),
+        ambiguous: position.x.abs() < POSITION_MARGIN
+            || position.y.abs() < POSITION_MARGIN
+            || !facing_field,
+    }
+}
@@ -0,0 +1,59 @@
+// Teach-and-repeat pose correction
+//
+// Recordings carry the pose the robot was at on every frame. During replay the recorded stick
+// input is used as feedforward, and a proportional holonomic term steers the robot back onto the
+// recorded trajectory.
+
+use evian::prelude::*;
+use replay::wrap;
+
+use crate::odometry::to_robot_frame;
+
+// Stick output per meter of position error.
+const POSITION_GAIN: f64 = 1.5;
+// Stick output per radian of heading error.
+const HEADING_GAIN: f64 = 0.8;
+// Largest correction added to any single axis.
+const MAX_CORRECTION: f64 = 0.35;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct PoseFollower {
+    input: (f64, f64, f64),
+    target: Option<(Vec2<f64>, Angle)>,
+}
+
+impl PoseFollower {
+    pub fn set_input(&mut self, x: f64, y: f64, r: f64) {
+        self.input = (x, y, r);
+    }
+
+    pub fn set_target(&mut self, position: Vec2<f64>, heading: Angle) {
+        self.target = Some((position, heading));
+    }
+
+    /// Recorded input plus the correction from `pose` toward the current target pose, as a
+    /// robot-relative strafe/forward vector and a rotation. Without a field pose there's nothing
+    /// to correct against, so the recorded input is passed through.
+    pub fn output(&self, pose: Option<(Vec2<f64>, Angle)>) -> (Vec2<f64>, f64) {
+        let (x, y, r) = self.input;
+
+        let (Some((target_position, target_heading)), Some((position, heading))) =
+            (self.target, pose)
+        else {
+            return (Vec2 { x, y }, r);
+        };
+
+        let error = to_robot_frame(target_position - position, heading);
+        let heading_error = wrap(target_heading.as_radians() - heading.as_radians());
+
+        let correct = |gain: f64, error: f64| (gain * error).clamp(-MAX_CORRECTION, MAX_CORRECTION);
+
+        (
+            Vec2 {
+                x: x + correct(POSITION_GAIN, error.x),
+                y: y + correct(POSITION_GAIN, error.y),
+            },
+            r + correct(HEADING_GAIN, heading_error),
+        )
+    }
+}
@@ -1,17 +1,95 @@
+// GPS + wheel odometry tracking
+//
+// A background task dead-reckons the pose from the four mecanum drive wheels at the motor update
+// rate, and pulls it toward the GPS whenever a trustworthy reading arrives. This is a
+// complementary filter: the wheels are smooth but drift, the GPS doesn't drift but is noisy and
+// slow, so poses between GPS updates come from the wheels and the GPS only corrects them.
+// Readings are weighted by the error the GPS reports for them, and readings that jump further
+// than the robot could have moved since the last one are thrown out, since the GPS can snap to a
+// wrong pose when its view of the field strips is blocked.
+//
+// When the GPS drops out the pose carries on from the wheels alone, and holds still if they can't
+// be read either. The GPS health is tracked so motion code can tell how far to trust the pose.
+//
+// Routes that know where the robot is, such as when it's squared against a wall, can reset the
+// pose. Resets are handed to the task and applied between updates, so a position and heading set
+// together never show up half applied. The GPS can be kept from correcting what was reset for a
+// while, or it would pull the pose straight back to its own reading.
+
 use alloc::rc::Rc;
-use core::cell::RefCell;
+use core::{cell::RefCell, time::Duration};
 
 use evian::{
     prelude::*,
     tracking::{RotarySensor, Tracking},
 };
+use replay::wrap;
 use vexide::{
-    devices::{math::Point2, smart::GpsSensor},
-    prelude::SmartDevice,
+    devices::smart::GpsSensor,
+    prelude::{Motor, SmartDevice},
     task::{Task, spawn},
     time::{Instant, sleep},
 };
 
+use crate::{EFFECTIVE_WHEELBASE, MAX_SPEED, TRACK_WIDTH, odometry::MecanumOdometry};
+
+// Fraction of the error to the GPS corrected on each GPS update.
+const POSITION_GAIN: f64 = 0.15;
+const HEADING_GAIN: f64 = 0.1;
+
+// GPS readings reporting more error than this, in meters, are ignored. Below it, the gains fall
+// off linearly with the reported error.
+const MAX_TRUSTED_ERROR: f64 = 0.05;
+
+// Readings weighted less than this still nudge the pose, but don't count toward the GPS health.
+const MIN_FIX_WEIGHT: f64 = 0.25;
+
+// Fastest the robot could plausibly move, in meters and radians per second, with some margin.
+// Readings implying faster movement since the last accepted one are rejected.
+const MAX_PLAUSIBLE_SPEED: f64 = MAX_SPEED * 1.25;
+const MAX_PLAUSIBLE_TURN_RATE: f64 =
+    MAX_PLAUSIBLE_SPEED / ((TRACK_WIDTH + EFFECTIVE_WHEELBASE) / 2.);
+
+// Jump allowed on top of the speed limit, in meters and radians, for the GPS's own noise.
+const POSITION_NOISE: f64 = 0.05;
+const HEADING_NOISE: f64 = 0.05;
+
+// How long since the last trusted GPS reading before the GPS counts as stale, then lost.
+const STALE_AFTER: Duration = Duration::from_millis(100);
+const LOST_AFTER: Duration = Duration::from_secs(1);
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpsHealth {
+    /// Recent trusted readings are correcting the pose.
+    Ok,
+    /// No trusted reading for a moment. The pose is from the wheels but has barely drifted.
+    Stale,
+    /// No trusted reading for a while, or ever. The pose is from the wheels alone.
+    #[default]
+    Lost,
+    /// The GPS isn't plugged in.
+    Disconnected,
+}
+
+impl GpsHealth {
+    /// Whether the pose is in field coordinates, rather than relative to wherever the robot was
+    /// turned on.
+    pub fn is_on_field(self) -> bool {
+        matches!(self, Self::Ok | Self::Stale)
+    }
+}
+
+/// A correction to the tracked pose. Parts left as `None` are kept as they are.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct PoseReset {
+    pub position: Option<Vec2<f64>>,
+    pub heading: Option<Angle>,
+    /// Whether to count forward travel from zero again.
+    pub zero_travel: bool,
+    /// How long the GPS is kept from correcting the parts that were set.
+    pub hold: Duration,
+}
+
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub(crate) struct TrackingData {
     position: Vec2<f64>,
@@ -19,92 +97,221 @@ pub(crate) struct TrackingData {
     forward_travel: f64,
     linear_velocity: f64,
     angular_velocity: f64,
+    health: GpsHealth,
+    rejected_samples: u32,
+    /// A reset the task hasn't applied yet.
+    reset: Option<PoseReset>,
 }
 
-pub struct GpsWheeledTracking<T: RotarySensor + 'static, const N: usize> {
+pub struct GpsWheeledTracking {
     data: Rc<RefCell<TrackingData>>,
-    forward_wheels: [TrackingWheel<T>; N],
     _task: Task<()>,
 }
 
-impl<T: RotarySensor + 'static, const N: usize> GpsWheeledTracking<T, N> {
-    pub fn new(gps: GpsSensor, forward_wheels: [TrackingWheel<T>; N]) -> Self {
-        const {
-            assert!(
-                N > 0,
-                "GPS + Wheeled tracking requires at least one forward tracking wheel to solve forward travel."
-            );
-        }
-
-        let p_position = gps.position().unwrap_or(Point2 { x: 0., y: 0. });
-        let p_heading = gps.heading().map_or(Angle::default(), Angle::from_degrees);
-
-        let data = Rc::new(RefCell::new(TrackingData {
-            position: p_position.into(),
-            heading: p_heading,
-            ..Default::default()
-        }));
-
+impl GpsWheeledTracking {
+    /// Tracks with a GPS and the four wheels of a mecanum drivetrain, given as front left, back
+    /// left, front right and back right.
+    pub fn new<T: RotarySensor + 'static>(
+        gps: GpsSensor,
+        wheels: [TrackingWheel<T>; 4],
+        track_width: f64,
+        wheelbase: f64,
+    ) -> Self {
+        // Until the GPS has a trusted reading or the pose is reset, the pose is only relative to
+        // where the robot started, so the first reading is taken whole rather than blended in.
+        let data = Rc::new(RefCell::new(TrackingData::default()));
         let task_data = data.clone();
 
         let task = spawn(async move {
-            let mut p_position = p_position;
+            let mut odometry = MecanumOdometry::new(wheels, track_width, wheelbase);
             let mut p_time = Instant::now();
+            let mut p_gps = p_time;
+            let mut last_fix: Option<Instant> = None;
+            let mut last_sample: Option<(Instant, Vec2<f64>, f64)> = None;
+            let mut localized = false;
+            let mut hold_position: Option<Instant> = None;
+            let mut hold_heading: Option<Instant> = None;
 
             loop {
-                sleep(GpsSensor::UPDATE_INTERVAL).await;
-
-                let position = gps.position().unwrap_or(Point2 { x: 0., y: 0. });
-                let heading = Angle::from_degrees(gps.heading().unwrap_or_default());
+                sleep(Motor::UPDATE_INTERVAL).await;
 
                 let time = Instant::now();
                 let dt = time.duration_since(p_time).as_secs_f64().max(1e-9);
 
-                let linear_velocity = Vec2::from(position).distance(Vec2::from(p_position)) / dt;
-                let angular_velocity = gps
-                    .gyro_rate()
-                    .map(|rate| rate.z)
-                    .unwrap_or_default()
-                    .to_radians();
+                // Predict from the wheels.
+                let displacement = odometry.update();
+
+                let reset = task_data.borrow_mut().reset.take();
+                if let Some(reset) = reset {
+                    odometry.set_pose(
+                        reset.position.unwrap_or(odometry.position()),
+                        reset.heading.unwrap_or(odometry.heading()),
+                    );
+                    if reset.zero_travel {
+                        odometry.zero_travel();
+                    }
+                    if reset.position.is_some() {
+                        hold_position = Some(time + reset.hold);
+                    }
+                    if reset.heading.is_some() {
+                        hold_heading = Some(time + reset.hold);
+                    }
+
+                    localized = true;
+                }
+
+                let (mut position, mut heading) = (odometry.position(), odometry.heading());
+
+                // Correct toward the GPS when it has a new reading it's confident in.
+                if time.duration_since(p_gps) >= GpsSensor::UPDATE_INTERVAL {
+                    p_gps = time;
 
-                {
-                    let mut d = task_data.borrow_mut();
+                    if let Ok(error) = gps.error()
+                        && error <= MAX_TRUSTED_ERROR
+                        && let (Ok(gps_position), Ok(gps_heading)) = (gps.position(), gps.heading())
+                    {
+                        let gps_position = Vec2 {
+                            x: gps_position.x,
+                            y: gps_position.y,
+                        };
+                        let gps_heading = Angle::from_degrees(gps_heading).as_radians();
 
-                    d.position = position.into();
-                    d.heading = heading;
+                        // Compared against the last accepted reading rather than the pose, so
+                        // wheel drift can't get good readings rejected.
+                        let plausible =
+                            last_sample.is_none_or(|(sample_time, p_position, p_heading)| {
+                                let elapsed = time.duration_since(sample_time).as_secs_f64();
 
-                    d.linear_velocity = linear_velocity;
-                    d.angular_velocity = angular_velocity;
+                                gps_position.distance(p_position)
+                                    <= MAX_PLAUSIBLE_SPEED * elapsed + POSITION_NOISE
+                                    && wrap(gps_heading - p_heading).abs()
+                                        <= MAX_PLAUSIBLE_TURN_RATE * elapsed + HEADING_NOISE
+                            });
+
+                        if plausible {
+                            last_sample = Some((time, gps_position, gps_heading));
+
+                            let heading_error = wrap(gps_heading - heading.as_radians());
+                            let weight = 1. - error / MAX_TRUSTED_ERROR;
+                            let (mut position_gain, mut heading_gain) = if localized {
+                                (POSITION_GAIN * weight, HEADING_GAIN * weight)
+                            } else {
+                                (1., 1.)
+                            };
+
+                            // Leave recently reset parts of the pose alone.
+                            if hold_position.is_some_and(|until| time < until) {
+                                position_gain = 0.;
+                            }
+                            if hold_heading.is_some_and(|until| time < until) {
+                                heading_gain = 0.;
+                            }
+
+                            position = Vec2 {
+                                x: position.x + (gps_position.x - position.x) * position_gain,
+                                y: position.y + (gps_position.y - position.y) * position_gain,
+                            };
+                            heading = Angle::from_radians(
+                                heading.as_radians() + heading_error * heading_gain,
+                            );
+                            odometry.set_pose(position, heading);
+
+                            if weight >= MIN_FIX_WEIGHT {
+                                last_fix = Some(time);
+                            }
+                            localized = true;
+                        } else {
+                            task_data.borrow_mut().rejected_samples += 1;
+                        }
+                    }
+
+                    task_data.borrow_mut().health = if !gps.is_connected() {
+                        GpsHealth::Disconnected
+                    } else {
+                        match last_fix.map(|fix| time.duration_since(fix)) {
+                            Some(age) if age < STALE_AFTER => GpsHealth::Ok,
+                            Some(age) if age < LOST_AFTER => GpsHealth::Stale,
+                            _ => GpsHealth::Lost,
+                        }
+                    };
                 }
 
-                p_position = position;
+                let mut d = task_data.borrow_mut();
+
+                d.position = position;
+                d.heading = heading;
+                d.forward_travel = odometry.forward_travel();
+
+                // From the wheels rather than the pose, so GPS corrections don't show up as
+                // bursts of speed.
+                d.linear_velocity = displacement.distance() / dt;
+
+                // The GPS gyro is smoother than the wheels, but they'll do without it.
+                d.angular_velocity = gps
+                    .gyro_rate()
+                    .map_or(displacement.rotation / dt, |rate| rate.z.to_radians());
+
+                drop(d);
+
                 p_time = time;
             }
         });
 
-        Self {
-            data,
-            forward_wheels,
-            _task: task,
+        Self { data, _task: task }
+    }
+
+    /// How well the GPS is keeping the pose on the field.
+    pub fn health(&self) -> GpsHealth {
+        self.data.borrow().health
+    }
+
+    /// How many GPS readings have been thrown out for jumping further than the robot could have
+    /// moved.
+    pub fn rejected_samples(&self) -> u32 {
+        self.data.borrow().rejected_samples
+    }
+
+    /// Overwrites the parts of the pose `reset` sets. The new pose reads back right away, and the
+    /// task carries on from it on its next update.
+    pub fn reset(&self, reset: PoseReset) {
+        let mut data = self.data.borrow_mut();
+
+        if let Some(position) = reset.position {
+            data.position = position;
+        }
+        if let Some(heading) = reset.heading {
+            data.heading = heading;
+        }
+        if reset.zero_travel {
+            data.forward_travel = 0.;
         }
+
+        // Merge with a reset the task hasn't got to yet, so neither is lost.
+        let pending = data.reset.take().unwrap_or_default();
+        data.reset = Some(PoseReset {
+            position: reset.position.or(pending.position),
+            heading: reset.heading.or(pending.heading),
+            zero_travel: reset.zero_travel || pending.zero_travel,
+            hold: reset.hold.max(pending.hold),
+        });
     }
 }
 
-impl<T: RotarySensor + 'static, const N: usize> Tracking for GpsWheeledTracking<T, N> {}
+impl Tracking for GpsWheeledTracking {}
 
-impl<T: RotarySensor + 'static, const N: usize> TracksPosition for GpsWheeledTracking<T, N> {
+impl TracksPosition for GpsWheeledTracking {
     fn position(&self) -> Vec2<f64> {
         self.data.borrow().position
     }
 }
 
-impl<T: RotarySensor + 'static, const N: usize> TracksHeading for GpsWheeledTracking<T, N> {
+impl TracksHeading for GpsWheeledTracking {
     fn heading(&self) -> Angle {
         self.data.borrow().heading
     }
 }
 
-impl<T: RotarySensor + 'static, const N: usize> TracksVelocity for GpsWheeledTracking<T, N> {
+impl TracksVelocity for GpsWheeledTracking {
     fn linear_velocity(&self) -> f64 {
         self.data.borrow().linear_velocity
     }
@@ -114,19 +321,9 @@ impl<T: RotarySensor + 'static, const N: usize> TracksVelocity for GpsWheeledTra
     }
 }
 
-impl<T: RotarySensor + 'static, const N: usize> TracksForwardTravel for GpsWheeledTracking<T, N> {
+impl TracksForwardTravel for GpsWheeledTracking {
     fn forward_travel(&self) -> f64 {
-        let mut sum = 0.0f64;
-        let mut cnt = 0usize;
-
-        for w in self.forward_wheels.iter() {
-            if let Ok(t) = w.travel() {
-                sum += t;
-                cnt += 1;
-            }
-        }
-
-        if cnt == 0 { 0.0 } else { sum / (cnt as f64) }
+        self.data.borrow().forward_travel
     }
 }
 
@@ -0,0 +1,74 @@
+// Hybrid routes
+//
+// A hybrid route is a sequence of stages run against one recording. Freehand replay segments can
+// be bracketed by motion-controlled moves onto poses the recording passed through, so errors from
+// one segment don't carry into the next, with mechanism actions and waits in between. Where the
+// robot is known to be on a recorded pose, tracking can be reset to it.
+
+use replay::{edit, path::Waypoint};
+
+use crate::auton::Event;
+
+// Speeds below which the drivetrain counts as stopped, in meters and radians per second.
+const STOPPED_LINEAR_VELOCITY: f64 = 0.02;
+const STOPPED_ANGULAR_VELOCITY: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    /// The route has been running for this many microseconds.
+    Elapsed(u64),
+    /// The drivetrain has come to rest.
+    Stopped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    /// Replays the recording between `from` and `to` microseconds, then stops the drivetrain.
+    Replay { from: u64, to: u64 },
+    /// Drives and turns with motion control to the pose the recording was at `at` microseconds
+    /// in.
+    MoveTo { at: u64 },
+    /// Turns with motion control to `heading` degrees, as recorded.
+    TurnTo { heading: f64 },
+    /// Resets tracking to the position, heading or both that the recording was at `at`
+    /// microseconds in, for when the robot is known to be there, such as against a wall. The GPS
+    /// doesn't correct them again for `hold` microseconds.
+    Assume {
+        at: u64,
+        position: bool,
+        heading: bool,
+        hold: u64,
+    },
+    /// Runs a mechanism event.
+    Mechanism(Event),
+    /// Waits until `condition` holds, or `timeout` microseconds pass.
+    WaitUntil { condition: Condition, timeout: u64 },
+}
+
+impl Condition {
+    /// Whether the condition holds `elapsed` microseconds into the route, with the drivetrain
+    /// moving at the given speeds.
+    pub fn holds(&self, elapsed: u64, linear_velocity: f64, angular_velocity: f64) -> bool {
+        match *self {
+            Self::Elapsed(micros) => elapsed >= micros,
+            Self::Stopped => {
+                linear_velocity.abs() < STOPPED_LINEAR_VELOCITY
+                    && angular_velocity.abs() < STOPPED_ANGULAR_VELOCITY
+            }
+        }
+    }
+}
+
+/// The last pose the recording noted at or before `at`.
+pub fn pose_at(events: &[Event], at: u64) -> Option<Waypoint> {
+    let (timeline, _) = edit::timeline(events);
+
+    timeline
+        .iter()
+        .take_while(|timed| timed.time <= at)
+        .filter_map(|timed| match timed.event {
+            Event::Pose { x, y, h } => Some(Waypoint { x, y, h }),
+            _ => None,
+        })
+        .last()
+}
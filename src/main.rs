@@ -1,26 +1,48 @@
 #![no_main]
 #![no_std]
 
-use alloc::rc::Rc;
-use core::{cell::RefCell, time::Duration};
-
-use autons::{
-    prelude::{SelectCompete, SelectCompeteExt},
-    route,
-    simple::SimpleSelect,
+use alloc::{borrow::Cow, vec::Vec};
+use core::{
+    f64::consts::{FRAC_PI_2, PI},
+    future::poll_fn,
+    pin::pin,
+    task::Poll,
+    time::Duration,
 };
+
 use evian::{
     control::loops::{AngularPid, Pid},
     drivetrain::model::Mecanum,
     motion::Basic,
     prelude::*,
 };
+use replay::{
+    edit,
+    path::{self, Step},
+};
 use vexide::{
-    devices::{math::Point2, smart::GpsSensor},
+    devices::{display::Display, math::Point2, smart::GpsSensor},
     prelude::*,
+    time::Instant,
 };
 
-use crate::{auton::FRAMES, gps::GpsWheeledTracking, mechanisms::ControlledMotorGroup, teams::*};
+use crate::{
+    clock::ReplayClock,
+    detect::Detection,
+    gps::{GpsWheeledTracking, PoseReset},
+    hybrid::Stage,
+    mechanisms::{ControlledMotorGroup, Mechanism},
+    mirror::Mirror,
+    placement::{Guidance, Placement},
+    player::{Outcome, Target},
+    practice,
+    preview::Preview,
+    recorder::{MechanismState, Recorder, Sample},
+    routes::{Plan, Playback, RECORDER_PATH},
+    selector::{Choice, Selector},
+    start::{StartError, StartPolicy},
+    teams::*,
+};
 
 const LINEAR_PID: Pid = Pid::new(1.0, 0.0, 0.125, None);
 const ANGULAR_PID: AngularPid = AngularPid::new(16.0, 0.0, 1.0, None);
@@ -33,11 +55,38 @@ const ANGULAR_TOLERANCES: Tolerances = Tolerances::new()
     .velocity(0.09)
     .duration(Duration::from_millis(15));
 
+const BASIC: Basic<Pid, AngularPid> = Basic {
+    linear_controller: LINEAR_PID,
+    angular_controller: ANGULAR_PID,
+    linear_tolerances: LINEAR_TOLERANCES,
+    angular_tolerances: ANGULAR_TOLERANCES,
+    timeout: Some(Duration::from_secs(10)),
+};
+
+// Waypoints closer than this, in meters, are already reached and only set the final heading.
+const WAYPOINT_SKIP_DISTANCE: f64 = 0.02;
+
 extern crate alloc;
 
 mod auton;
+mod battery;
+mod clock;
+mod detect;
+mod follower;
 mod gps;
+mod hybrid;
 mod mechanisms;
+mod mirror;
+mod odometry;
+mod placement;
+mod player;
+mod practice;
+mod preview;
+mod recorder;
+mod recording;
+mod routes;
+mod selector;
+mod start;
 mod teams;
 
 pub const INCH_TO_METER: f64 = 0.0254;
@@ -45,6 +94,21 @@ pub const INCH_TO_METER: f64 = 0.0254;
 pub const BALL_DIAMETER: f64 = 3.25 * INCH_TO_METER;
 pub const WHEEL_DIAMETER: f64 = 4. * INCH_TO_METER;
 pub const TRACK_WIDTH: f64 = 14. * INCH_TO_METER;
+// Wheel rotations per rotation of the robot turning in place. This is calibrated, not worked out
+// from the frame: spin the robot ten times in place, divide the drive motors' average rotations by
+// ten, and put the result here. The current value is the team's hand-tuned turn constant from
+// `ROBOT_TO_WHEEL_ROT` below and should be replaced by a calibrated one.
+const WHEEL_TURNS_PER_ROBOT_TURN: f64 = 2100. / 360.;
+// Front to back wheel spacing the mecanum turning model uses. A mecanum robot turns its wheels
+// (TRACK_WIDTH + EFFECTIVE_WHEELBASE) / WHEEL_DIAMETER times as far as itself, so this is solved
+// from the calibrated turn ratio rather than measured, and folds in wheel slip while turning.
+pub const EFFECTIVE_WHEELBASE: f64 = WHEEL_TURNS_PER_ROBOT_TURN * WHEEL_DIAMETER - TRACK_WIDTH;
+
+// Green cartridge free speed.
+const MOTOR_RPM: f64 = 200.;
+
+/// Wheel surface speed at full input, in meters per second.
+pub const MAX_SPEED: f64 = MOTOR_RPM / 60. * PI * WHEEL_DIAMETER;
 
 // To rotate the body of the robot N degrees, spin the left/right wheels by ROBOT_TO_WHEEL_ROT * N,
 // and the opposite side by -ROBOT_TO_WHEEL_ROT * N degrees. Swap which wheels get the negative to
@@ -58,70 +122,680 @@ pub struct Robot {
     intake: ControlledMotorGroup<2>,
     router: ControlledMotorGroup<1>,
 
-    drivetrain: Drivetrain<Mecanum, GpsWheeledTracking<Rc<RefCell<[Motor; 1]>>, 4>>,
+    drivetrain: Drivetrain<Mecanum, GpsWheeledTracking>,
+
+    recorder: Recorder,
+
+    /// Route last run or previewed, replayed again by the practice combo.
+    last_route: Option<(Alliance, Side)>,
+
+    /// Starting tile read off the GPS while disabled, once it's certain or has been confirmed.
+    detected_route: Option<(Alliance, Side)>,
+
+    selector: Selector,
+    display: Display,
 }
 
 impl Robot {
-    async fn autonomous(&mut self, alliance: Alliance, side: Side) {
-        if side == Side::Left {
+    async fn run_route(&mut self, alliance: Alliance, side: Side) {
+        self.last_route = Some((alliance, side));
+
+        let Some(plan) = routes::find(alliance, side).and_then(|route| route.load()) else {
+            return;
+        };
+
+        let mut basic = BASIC;
+
+        if !self.verify_start(&plan, &mut basic).await {
             return;
         }
 
-        let dt = &mut self.drivetrain;
+        match plan.playback {
+            Playback::Replay => self.replay(&plan.events, plan.mirror).await,
+            Playback::Path { tolerance } => {
+                let steps = path::to_path(&plan.events, tolerance);
+                self.follow_path(&mut basic, &steps, plan.mirror).await;
+            }
+            Playback::Hybrid(stages) => {
+                self.run_hybrid(&mut basic, &plan.events, stages, plan.mirror)
+                    .await;
+            }
+        }
+
+        println!(
+            "GPS has rejected {} readings as outliers",
+            self.drivetrain.tracking.rejected_samples()
+        );
+    }
 
-        let mut basic = Basic {
-            linear_controller: LINEAR_PID,
-            angular_controller: ANGULAR_PID,
-            linear_tolerances: LINEAR_TOLERANCES,
-            angular_tolerances: ANGULAR_TOLERANCES,
-            timeout: Some(Duration::from_secs(10)),
+    /// Checks the robot is where the route starts, handling a mismatch by the route's policy.
+    /// Returns whether the route should run.
+    async fn verify_start(&mut self, plan: &Plan, basic: &mut Basic<Pid, AngularPid>) -> bool {
+        let Some((position, heading)) = plan.start_pose() else {
+            return true;
         };
 
-        basic.turn_to_heading(dt, Angle::from_degrees(100.)).await;
+        let tracking = &self.drivetrain.tracking;
 
-        for frame in FRAMES {
-            match frame {
-                auton::Event::Input { x, y, r } => {
-                    self.drivetrain.model.drive_vector(Vec2 { x, y }, r).ok();
+        // Without the GPS the pose is relative to wherever the robot was turned on, so there's
+        // nothing to compare against.
+        if !tracking.health().is_on_field() {
+            println!("Skipping the start check, GPS is {:?}", tracking.health());
+            return true;
+        }
+
+        let error = StartError::new(
+            (position, heading),
+            (tracking.position(), tracking.heading()),
+        );
+
+        if error.is_within_tolerance() {
+            return true;
+        }
+
+        println!(
+            "Robot is {:.3} m and {:.1} deg from the route start, {:?}",
+            error.distance,
+            error.heading.to_degrees(),
+            plan.start_policy
+        );
+
+        match plan.start_policy {
+            StartPolicy::Refuse => {
+                self.controller
+                    .screen
+                    .try_set_text("Bad start  ", 1, 1)
+                    .ok();
+                false
+            }
+            StartPolicy::Warn => {
+                let warning = alloc::format!(
+                    "Off {:.0}cm {:.0}deg",
+                    error.distance * 100.,
+                    error.heading.to_degrees()
+                );
+                self.controller.screen.try_set_text(warning, 1, 1).ok();
+                true
+            }
+            StartPolicy::Align => {
+                Self::move_to(&mut self.drivetrain, basic, position).await;
+                basic.turn_to_heading(&mut self.drivetrain, heading).await;
+                true
+            }
+        }
+    }
+
+    async fn replay(&mut self, frames: &[auton::Event], mirror: Mirror) {
+        let mut target = RobotTarget {
+            robot: self,
+            clock: ReplayClock::start(),
+            abortable: false,
+        };
+
+        player::play(&mut target, frames, mirror).await;
+
+        let lateness = target.clock.lateness();
+        println!(
+            "Replay finished, lateness max {:?} mean {:?} over {} waits",
+            lateness.max, lateness.mean, lateness.waits
+        );
+    }
+
+    async fn follow_path(
+        &mut self,
+        basic: &mut Basic<Pid, AngularPid>,
+        steps: &[Step],
+        mirror: Mirror,
+    ) {
+        let mut final_heading = None;
+
+        for &step in steps {
+            match step {
+                Step::MoveTo(waypoint) => {
+                    let (target, heading) = mirror.pose(
+                        Vec2 {
+                            x: waypoint.x,
+                            y: waypoint.y,
+                        },
+                        Angle::from_radians(waypoint.h),
+                    );
+                    final_heading = Some(heading);
+
+                    Self::move_to(&mut self.drivetrain, basic, target).await;
                 }
-                auton::Event::Wait(micros) => {
-                    sleep(Duration::from_micros(micros)).await;
+                Step::Event(event) => self.run_mechanism(event),
+            }
+        }
+
+        if let Some(heading) = final_heading {
+            basic.turn_to_heading(&mut self.drivetrain, heading).await;
+        }
+    }
+
+    /// Replays the last route from driver control until it finishes or the driver takes over.
+    /// Without one, it's the route for the tile the robot is on. Path routes are refused, since
+    /// their files only hold waypoints, with no inputs or timing to replay.
+    ///
+    /// Returning plays the route backwards to carry the robot back to its start, then squares up
+    /// on the recorded start pose with motion control if the GPS has the robot on the field.
+    async fn practice_replay(&mut self, mode: practice::Mode) {
+        let Some(plan) = self
+            .last_route
+            .or_else(|| self.starting_tile())
+            .and_then(|(alliance, side)| routes::find(alliance, side))
+            .and_then(|route| route.load())
+        else {
+            self.controller
+                .screen
+                .try_set_text("No route   ", 1, 1)
+                .ok();
+            return;
+        };
+
+        if let Playback::Path { .. } = plan.playback {
+            self.controller
+                .screen
+                .try_set_text("Path route ", 1, 1)
+                .ok();
+            return;
+        }
+
+        let (events, status) = match mode {
+            practice::Mode::Replay => (Cow::Borrowed(&*plan.events), "Replaying  "),
+            practice::Mode::Return => (Cow::Owned(edit::reverse(&plan.events)), "Returning  "),
+        };
+        self.controller.screen.try_set_text(status, 1, 1).ok();
+
+        // The reversed route has no mechanism events, so nothing would turn off what the driver
+        // left running.
+        if mode == practice::Mode::Return {
+            self.stop();
+        }
+
+        let mut target = RobotTarget {
+            robot: self,
+            clock: ReplayClock::start(),
+            abortable: true,
+        };
+
+        let mut outcome = player::play(&mut target, &events, plan.mirror).await;
+
+        self.stop();
+
+        if mode == practice::Mode::Return
+            && outcome == Outcome::Finished
+            && let Some((position, heading)) = plan.start_pose()
+        {
+            // The start pose is in field coordinates, which the wheels alone don't know.
+            let health = self.drivetrain.tracking.health();
+            if health.is_on_field() {
+                let mut basic = BASIC;
+                let drivetrain = &mut self.drivetrain;
+                outcome = unless_aborted(&self.controller, async {
+                    Self::move_to(drivetrain, &mut basic, position).await;
+                    basic.turn_to_heading(drivetrain, heading).await;
+                })
+                .await;
+                self.stop();
+            } else {
+                println!("Skipping the square-up, GPS is {health:?}");
+            }
+        }
+
+        let status = match outcome {
+            Outcome::Finished => "Finished   ",
+            Outcome::Aborted => "Aborted    ",
+        };
+        self.controller.screen.try_set_text(status, 1, 1).ok();
+    }
+
+    /// Stops the drivetrain and every mechanism.
+    fn stop(&mut self) {
+        self.drivetrain
+            .model
+            .drive_vector(Vec2 { x: 0., y: 0. }, 0.)
+            .ok();
+        self.intake.disable().ok();
+        self.router.disable().ok();
+    }
+
+    async fn run_hybrid(
+        &mut self,
+        basic: &mut Basic<Pid, AngularPid>,
+        events: &[auton::Event],
+        stages: &[Stage],
+        mirror: Mirror,
+    ) {
+        let start = Instant::now();
+
+        for &stage in stages {
+            match stage {
+                Stage::Replay { from, to } => {
+                    self.replay(&edit::slice(events, from, to), mirror).await;
+                    self.drivetrain
+                        .model
+                        .drive_vector(Vec2 { x: 0., y: 0. }, 0.)
+                        .ok();
+                }
+                Stage::MoveTo { at } => {
+                    let Some(waypoint) = hybrid::pose_at(events, at) else {
+                        println!("No recorded pose to move to at {at}us");
+                        continue;
+                    };
+                    let (target, heading) = mirror.pose(
+                        Vec2 {
+                            x: waypoint.x,
+                            y: waypoint.y,
+                        },
+                        Angle::from_radians(waypoint.h),
+                    );
+
+                    Self::move_to(&mut self.drivetrain, basic, target).await;
+                    basic.turn_to_heading(&mut self.drivetrain, heading).await;
                 }
-                auton::Event::IntakeFwd => {
-                    self.intake.forward().ok();
+                Stage::TurnTo { heading } => {
+                    let heading = mirror.heading(Angle::from_degrees(heading));
+                    basic.turn_to_heading(&mut self.drivetrain, heading).await;
                 }
-                auton::Event::IntakeRev => {
-                    self.intake.reverse().ok();
+                Stage::Assume {
+                    at,
+                    position,
+                    heading,
+                    hold,
+                } => {
+                    let Some(waypoint) = hybrid::pose_at(events, at) else {
+                        println!("No recorded pose to assume at {at}us");
+                        continue;
+                    };
+                    let (recorded_position, recorded_heading) = mirror.pose(
+                        Vec2 {
+                            x: waypoint.x,
+                            y: waypoint.y,
+                        },
+                        Angle::from_radians(waypoint.h),
+                    );
+
+                    self.drivetrain.tracking.reset(PoseReset {
+                        position: position.then_some(recorded_position),
+                        heading: heading.then_some(recorded_heading),
+                        zero_travel: false,
+                        hold: Duration::from_micros(hold),
+                    });
                 }
-                auton::Event::IntakeDisable => {
-                    self.intake.disable().ok();
+                Stage::Mechanism(event) => self.run_mechanism(event),
+                Stage::WaitUntil { condition, timeout } => {
+                    let deadline = Instant::now() + Duration::from_micros(timeout);
+
+                    while Instant::now() < deadline {
+                        let tracking = &self.drivetrain.tracking;
+                        let elapsed = start.elapsed().as_micros() as u64;
+
+                        if condition.holds(
+                            elapsed,
+                            tracking.linear_velocity(),
+                            tracking.angular_velocity(),
+                        ) {
+                            break;
+                        }
+
+                        sleep(Controller::UPDATE_INTERVAL).await;
+                    }
                 }
             }
         }
     }
 
-    async fn route_red_left(&mut self) {
-        self.autonomous(Alliance::Red, Side::Left).await
+    /// Turns to face `target` and drives straight to it, unless it's already reached.
+    async fn move_to(
+        drivetrain: &mut Drivetrain<Mecanum, GpsWheeledTracking>,
+        basic: &mut Basic<Pid, AngularPid>,
+        target: Vec2<f64>,
+    ) {
+        let position = drivetrain.tracking.position();
+        let distance = target.distance(position);
+
+        if distance < WAYPOINT_SKIP_DISTANCE {
+            return;
+        }
+
+        // Face the waypoint; GPS headings are clockwise from +y.
+        let facing = Angle::from_radians(FRAC_PI_2 - (target - position).angle());
+
+        basic.turn_to_heading(drivetrain, facing).await;
+        basic.drive_distance(drivetrain, distance).await;
+    }
+
+    fn run_mechanism(&mut self, event: auton::Event) {
+        match event {
+            auton::Event::IntakeFwd => {
+                self.intake.forward().ok();
+            }
+            auton::Event::IntakeRev => {
+                self.intake.reverse().ok();
+            }
+            auton::Event::IntakeDisable => {
+                self.intake.disable().ok();
+            }
+            auton::Event::RouterFwd => {
+                self.router.forward().ok();
+            }
+            auton::Event::RouterRev => {
+                self.router.reverse().ok();
+            }
+            auton::Event::RouterDisable => {
+                self.router.disable().ok();
+            }
+            auton::Event::SetVoltage(Mechanism::Intake, volts) => {
+                self.intake.set_voltage(volts).ok();
+            }
+            auton::Event::SetVoltage(Mechanism::Router, volts) => {
+                self.router.set_voltage(volts).ok();
+            }
+            auton::Event::Input { .. }
+            | auton::Event::Wait(_)
+            | auton::Event::Pose { .. }
+            | auton::Event::Battery(_) => {}
+        }
+    }
+
+    async fn preview(&mut self, alliance: Alliance, side: Side) {
+        self.last_route = Some((alliance, side));
+
+        let Some(plan) = routes::find(alliance, side).and_then(|route| route.load()) else {
+            return;
+        };
+
+        let mut preview = Preview::new(plan.start_pose());
+
+        match plan.playback {
+            Playback::Replay => {
+                player::play(&mut preview, &plan.events, plan.mirror).await;
+            }
+            Playback::Path { tolerance } => {
+                preview.trace_path(&path::to_path(&plan.events, tolerance), plan.mirror)
+            }
+            Playback::Hybrid(stages) => {
+                preview
+                    .trace_hybrid(&plan.events, stages, plan.mirror)
+                    .await
+            }
+        }
+
+        preview.draw(&mut self.display, alliance);
     }
 
-    async fn route_red_right(&mut self) {
-        self.autonomous(Alliance::Red, Side::Right).await
+    /// Tile the robot is on: the one the GPS can see it on, or else the selected route's.
+    fn starting_tile(&self) -> Option<(Alliance, Side)> {
+        let tracking = &self.drivetrain.tracking;
+
+        if tracking.health().is_on_field() {
+            let detection = detect::detect(tracking.position(), tracking.heading());
+            if !detection.ambiguous {
+                return Some(detection.route);
+            }
+        }
+
+        match self.selector.choice() {
+            Choice::Run(alliance, side) | Choice::Preview(alliance, side) => Some((alliance, side)),
+            Choice::Auto => self.detected_route,
+        }
     }
 
-    async fn route_blue_left(&mut self) {
-        self.autonomous(Alliance::Blue, Side::Left).await
+    /// Runs the route for the tile the robot was detected on.
+    async fn route_auto(&mut self) {
+        let tracking = &self.drivetrain.tracking;
+
+        // As with the start check, a pose the GPS doesn't have says nothing about the tile.
+        let health = tracking.health();
+        let detection = health
+            .is_on_field()
+            .then(|| detect::detect(tracking.position(), tracking.heading()));
+
+        let route = self.detected_route.or(detection
+            .filter(|detection| !detection.ambiguous)
+            .map(|detection| detection.route));
+
+        match (route, detection) {
+            (Some((alliance, side)), _) => self.run_route(alliance, side).await,
+            (None, Some(detection)) => {
+                println!(
+                    "Starting tile looks like {:?} but wasn't confirmed",
+                    detection.route
+                );
+                self.controller
+                    .screen
+                    .try_set_text("Unconfirmed", 1, 1)
+                    .ok();
+            }
+            (None, None) => {
+                println!("Starting tile unknown, GPS {health:?}");
+                self.controller
+                    .screen
+                    .try_set_text("No GPS     ", 1, 1)
+                    .ok();
+            }
+        }
+    }
+}
+
+/// Runs a motion until it settles or the driver takes over, as in practice replays. The abort is
+/// checked each time the motion wakes for its next control step.
+async fn unless_aborted(controller: &Controller, motion: impl Future<Output = ()>) -> Outcome {
+    let mut motion = pin!(motion);
+
+    poll_fn(|cx| {
+        if RobotTarget::abort_requested(controller) {
+            return Poll::Ready(Outcome::Aborted);
+        }
+
+        motion.as_mut().poll(cx).map(|()| Outcome::Finished)
+    })
+    .await
+}
+
+/// Plays recordings on the real robot.
+struct RobotTarget<'a> {
+    robot: &'a mut Robot,
+    clock: ReplayClock,
+    /// Whether the driver can take over, as in practice replays.
+    abortable: bool,
+}
+
+impl RobotTarget<'_> {
+    fn abort_requested(controller: &Controller) -> bool {
+        controller
+            .state()
+            .is_ok_and(|state| practice::abort_requested(&state))
+    }
+}
+
+impl Target for RobotTarget<'_> {
+    fn pose(&self) -> Option<(Vec2<f64>, Angle)> {
+        let tracking = &self.robot.drivetrain.tracking;
+        tracking
+            .health()
+            .is_on_field()
+            .then(|| (tracking.position(), tracking.heading()))
+    }
+
+    fn drive(&mut self, vector: Vec2<f64>, r: f64) {
+        self.robot.drivetrain.model.drive_vector(vector, r).ok();
+    }
+
+    fn mechanism(&mut self, event: auton::Event) {
+        self.robot.run_mechanism(event);
+    }
+
+    async fn wait(&mut self, micros: u64) {
+        if self.abortable {
+            let controller = &self.robot.controller;
+            self.clock
+                .wait_unless(micros, || Self::abort_requested(controller))
+                .await;
+        } else {
+            self.clock.wait(micros).await;
+        }
     }
 
-    async fn route_blue_right(&mut self) {
-        self.autonomous(Alliance::Blue, Side::Right).await
+    fn aborted(&mut self) -> bool {
+        self.abortable && Self::abort_requested(&self.robot.controller)
     }
 }
 
-impl SelectCompete for Robot {
+impl Compete for Robot {
+    async fn disabled(&mut self) {
+        let placements: Vec<Placement> = routes::ROUTES
+            .iter()
+            .filter_map(|route| {
+                let (position, heading) = route.load()?.start_pose()?;
+                Some(Placement {
+                    route: route.start,
+                    position,
+                    heading,
+                })
+            })
+            .collect();
+
+        let mut line = 0;
+        let mut show_on_brain = false;
+        let mut redraw_selector = true;
+        let mut confirmed: Option<(Alliance, Side)> = None;
+        let mut preselected: Option<(Alliance, Side)> = None;
+
+        loop {
+            let controller_state = self.controller.state().unwrap_or_default();
+
+            // Press A to swap the brain screen between the selector and placement guidance. The
+            // selector ignores touches while it's swapped out.
+            if controller_state.button_a.is_now_pressed() {
+                show_on_brain = !show_on_brain;
+                redraw_selector = !show_on_brain;
+            }
+
+            if !show_on_brain && (self.selector.update(&self.display) || redraw_selector) {
+                self.selector.draw(&mut self.display);
+                redraw_selector = false;
+            }
+
+            let tracking = &self.drivetrain.tracking;
+            let (position, heading) = (tracking.position(), tracking.heading());
+
+            // The pose means nothing on the field until the GPS has it.
+            let health = tracking.health();
+            if !health.is_on_field() {
+                self.detected_route = None;
+                placement::show_line(
+                    &mut self.controller.screen,
+                    &alloc::format!("GPS {health:?}"),
+                    0,
+                );
+
+                sleep(placement::UPDATE_INTERVAL).await;
+                continue;
+            }
+
+            // Press Y to confirm an ambiguous tile. Moving the robot onto another tile drops the
+            // confirmation.
+            let Detection {
+                route: detected,
+                ambiguous,
+            } = detect::detect(position, heading);
+            if confirmed.is_some_and(|confirmed| confirmed != detected) {
+                confirmed = None;
+            }
+            if ambiguous && controller_state.button_y.is_now_pressed() {
+                confirmed = Some(detected);
+            }
+
+            let certain = !ambiguous || confirmed.is_some();
+            self.detected_route = certain.then_some(detected);
+
+            // Select the route for a newly detected tile, so it's already picked when the robot is
+            // placed. It stays overridable: another tap sticks until the robot moves tiles.
+            // Previews are left alone since they never drive.
+            if certain
+                && preselected != Some(detected)
+                && !matches!(self.selector.choice(), Choice::Preview(..))
+            {
+                preselected = Some(detected);
+                let (alliance, side) = detected;
+                redraw_selector |= self.selector.select(Choice::Run(alliance, side));
+            }
+
+            // Guide toward the selected route's start. Only `Auto (GPS)` goes by the tile the
+            // robot is on.
+            let route = match self.selector.choice() {
+                Choice::Run(alliance, side) | Choice::Preview(alliance, side) => (alliance, side),
+                Choice::Auto => detected,
+            };
+
+            let (alliance, side) = route;
+            let title = if certain && route != detected {
+                let (alliance, side) = detected;
+                let side = match side {
+                    Side::Left => 'L',
+                    Side::Right => 'R',
+                };
+                alloc::format!("Wrong tile: {alliance:?} {side}")
+            } else if certain || route != detected {
+                alloc::format!("{alliance:?} {side:?}")
+            } else {
+                alloc::format!("{alliance:?} {side:?}? Y=ok")
+            };
+
+            match placements.iter().find(|placement| placement.route == route) {
+                Some(placement) => {
+                    let guidance = Guidance::new(placement, position, heading);
+
+                    guidance.show(&mut self.controller.screen, title, line);
+                    line = (line + 1) % 3;
+
+                    if show_on_brain {
+                        guidance.draw(&mut self.display, placement, position, heading);
+                    }
+                }
+                // Without a recorded start pose there's nothing to guide toward.
+                None => placement::show_line(&mut self.controller.screen, &title, 0),
+            }
+
+            sleep(placement::UPDATE_INTERVAL).await;
+        }
+    }
+
+    async fn autonomous(&mut self) {
+        match self.selector.choice() {
+            Choice::Auto => self.route_auto().await,
+            Choice::Run(alliance, side) => self.run_route(alliance, side).await,
+            Choice::Preview(alliance, side) => self.preview(alliance, side).await,
+        }
+    }
+
     async fn driver(&mut self) {
+        let mut combo_held = false;
+        let mut redraw_selector = true;
+
         loop {
             let controller_state = self.controller.state().unwrap_or_default();
 
+            // Without competition control the robot never gets disabled, so the route for
+            // practice replays and recordings is picked here.
+            if self.selector.update(&self.display) || redraw_selector {
+                self.selector.draw(&mut self.display);
+                redraw_selector = false;
+            }
+
+            // Practice replays start on the rising edge of a combo, and never mid-recording.
+            let mode = practice::requested_mode(&controller_state);
+            if let Some(mode) = mode
+                && !combo_held
+                && !self.recorder.is_recording()
+            {
+                combo_held = true;
+                self.practice_replay(mode).await;
+                continue;
+            }
+            combo_held = mode.is_some();
+
             // Forward/Back
             let lx = controller_state.right_stick.x();
 
@@ -147,6 +821,56 @@ impl SelectCompete for Robot {
             self.intake.drive_by_buttons(int_fw, int_bw).ok();
             self.router.drive_by_buttons(rou_fw, rou_bw).ok();
 
+            // Press X + A together to start/stop recording a route to the SD card. It's saved as
+            // the starting tile's own recording, so it's only ever replayed from that tile.
+            let record_combo =
+                controller_state.button_x.is_pressed() && controller_state.button_a.is_pressed();
+            let record_path = self
+                .starting_tile()
+                .and_then(|(alliance, side)| routes::find(alliance, side))
+                .and_then(|route| route.recording_path())
+                .unwrap_or(RECORDER_PATH);
+
+            match self.recorder.update_combo(record_combo, record_path) {
+                Some(Ok(path)) => {
+                    println!("Saved recording to {path}");
+
+                    // The tile may now have its own recording instead of a mirrored one.
+                    let choice = self.selector.choice();
+                    self.selector = Selector::new(&routes::ROUTES);
+                    self.selector.select(choice);
+                    redraw_selector = true;
+
+                    self.controller
+                        .screen
+                        .try_set_text("Saved      ", 1, 1)
+                        .ok();
+                }
+                Some(Err(err)) => {
+                    println!("Failed to save recording: {err:?}");
+                    self.controller
+                        .screen
+                        .try_set_text("Save failed", 1, 1)
+                        .ok();
+                }
+                None if record_combo && self.recorder.is_recording() => {
+                    self.controller
+                        .screen
+                        .try_set_text("Recording  ", 1, 1)
+                        .ok();
+                }
+                None => {}
+            }
+
+            self.recorder.capture(Sample {
+                input: (lx, ly, rx),
+                intake: MechanismState::from_buttons(int_fw, int_bw),
+                router: MechanismState::from_buttons(rou_fw, rou_bw),
+                position: self.drivetrain.tracking.position(),
+                heading: self.drivetrain.tracking.heading(),
+                battery: vexide::devices::battery::voltage(),
+            });
+
             sleep(Controller::UPDATE_INTERVAL).await;
         }
     }
@@ -197,6 +921,8 @@ async fn main(peripherals: Peripherals) {
                 TrackingWheel::new(front_right_motors, WHEEL_DIAMETER, TRACK_WIDTH / 2., None),
                 TrackingWheel::new(back_right_motors, WHEEL_DIAMETER, TRACK_WIDTH / 2., None),
             ],
+            TRACK_WIDTH,
+            EFFECTIVE_WHEELBASE,
         ),
     );
 
@@ -207,17 +933,15 @@ async fn main(peripherals: Peripherals) {
         router: ControlledMotorGroup::new(Motor::V5_MAX_VOLTAGE, [router]),
 
         drivetrain,
+
+        recorder: Recorder::default(),
+
+        last_route: None,
+        detected_route: None,
+
+        selector: Selector::new(&routes::ROUTES),
+        display: peripherals.display,
     };
 
-    robot
-        .compete(SimpleSelect::new(
-            peripherals.display,
-            [
-                // route!("Red, Left (NON-FUNCTIONAL)", Robot::route_red_left),
-                route!("Red (Right)", Robot::route_red_right),
-                // route!("Blue, Left (NON-FUNCTIONAL)", Robot::route_blue_left),
-                route!("Blue (Right)", Robot::route_blue_right),
-            ],
-        ))
-        .await;
+    robot.compete().await;
 }
@@ -1,3 +1,4 @@
+pub use replay::Mechanism;
 use vexide::{devices::smart::motor::MotorError, prelude::*};
 
 pub struct ControlledMotorGroup<const N: usize> {
@@ -0,0 +1,70 @@
+// Alliance/side mirroring for replayed routes
+//
+// Field coordinates follow the GPS: origin at the field center, red on -x and blue on +x. Sides
+// are named by the drive team standing at their own wall, so Red (Left) is on +y but Blue (Left)
+// is on -y. Switching alliance turns the field 180° about its center, which leaves the robot's
+// inputs untouched. Switching side reflects the field across y = 0, which flips the robot's
+// handedness, so strafe and rotation are negated.
+
+use evian::prelude::*;
+
+use crate::teams::{Alliance, Side};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mirror {
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl Mirror {
+    /// Transform from the starting position a route was recorded at to the one it is being run
+    /// from.
+    pub fn new(recorded: (Alliance, Side), target: (Alliance, Side)) -> Self {
+        let alliance = recorded.0 != target.0;
+        let side = recorded.1 != target.1;
+
+        Self {
+            flip_x: alliance,
+            flip_y: alliance != side,
+        }
+    }
+
+    pub fn is_reflection(&self) -> bool {
+        self.flip_x != self.flip_y
+    }
+
+    /// Mirrors robot-relative strafe and rotation inputs.
+    pub fn input(&self, x: f64, y: f64, r: f64) -> (f64, f64, f64) {
+        if self.is_reflection() {
+            (-x, y, -r)
+        } else {
+            (x, y, r)
+        }
+    }
+
+    pub fn position(&self, position: Vec2<f64>) -> Vec2<f64> {
+        Vec2 {
+            x: if self.flip_x { -position.x } else { position.x },
+            y: if self.flip_y { -position.y } else { position.y },
+        }
+    }
+
+    /// Mirrors a GPS (compass, clockwise from +y) heading.
+    pub fn heading(&self, heading: Angle) -> Angle {
+        let mut heading = heading;
+
+        if self.flip_x {
+            heading = -heading;
+        }
+
+        if self.flip_y {
+            heading = Angle::from_degrees(180.) - heading;
+        }
+
+        heading
+    }
+
+    pub fn pose(&self, position: Vec2<f64>, heading: Angle) -> (Vec2<f64>, Angle) {
+        (self.position(position), self.heading(heading))
+    }
+}
@@ -0,0 +1,157 @@
+// Mecanum wheel odometry
+//
+// Solves how far the robot moved from the change in travel of the four drive wheels. Each wheel's
+// travel is its encoder rotation times the wheel circumference, so the wheels need to be set up
+// with `WHEEL_DIAMETER`. Rotation uses the standard mecanum model, where turning depends on both
+// how far apart the wheels are side to side (`track_width`) and front to back (`wheelbase`). The
+// preview drives the same model.
+//
+// `MecanumOdometry` can track a pose on its own, or just report displacements for another tracker
+// to integrate, as `GpsWheeledTracking` does between GPS corrections.
+
+use evian::{prelude::*, tracking::RotarySensor};
+
+/// A field vector as seen by a robot facing `heading`, with x to its right and y ahead of it.
+pub fn to_robot_frame(vector: Vec2<f64>, heading: Angle) -> Vec2<f64> {
+    // GPS headings are clockwise from +y, so forward is (sin, cos) and right is (cos, -sin).
+    let (sin, cos) = (heading.sin(), heading.cos());
+
+    Vec2 {
+        x: vector.x * cos - vector.y * sin,
+        y: vector.x * sin + vector.y * cos,
+    }
+}
+
+/// Undoes `to_robot_frame`, turning a robot-relative vector back into a field one.
+pub fn to_field_frame(vector: Vec2<f64>, heading: Angle) -> Vec2<f64> {
+    let (sin, cos) = (heading.sin(), heading.cos());
+
+    Vec2 {
+        x: vector.x * cos + vector.y * sin,
+        y: vector.y * cos - vector.x * sin,
+    }
+}
+
+/// Robot-relative movement over one update.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Displacement {
+    /// Meters forward (negative is back).
+    pub forward: f64,
+    /// Meters right (negative is left).
+    pub strafe: f64,
+    /// Radians clockwise, matching GPS headings.
+    pub rotation: f64,
+}
+
+impl Displacement {
+    /// Solves the mecanum forward kinematics for the change in travel of the front left, back
+    /// left, front right and back right wheels.
+    pub fn from_wheels(delta: [f64; 4], track_width: f64, wheelbase: f64) -> Self {
+        let [fl, bl, fr, br] = delta;
+
+        Self {
+            forward: (fl + bl + fr + br) / 4.,
+            strafe: (fl - bl - fr + br) / 4.,
+            rotation: (fl + bl - fr - br) / 4. / ((track_width + wheelbase) / 2.),
+        }
+    }
+
+    /// Straight-line distance covered, in meters.
+    pub fn distance(&self) -> f64 {
+        Vec2 {
+            x: self.strafe,
+            y: self.forward,
+        }
+        .distance(Vec2 { x: 0., y: 0. })
+    }
+
+    /// Moves a field pose by this displacement, along the heading halfway through the turn.
+    pub fn apply(&self, position: Vec2<f64>, heading: Angle) -> (Vec2<f64>, Angle) {
+        let midpoint = Angle::from_radians(heading.as_radians() + self.rotation / 2.);
+        let movement = Vec2 {
+            x: self.strafe,
+            y: self.forward,
+        };
+
+        (
+            position + to_field_frame(movement, midpoint),
+            Angle::from_radians(heading.as_radians() + self.rotation),
+        )
+    }
+}
+
+pub struct MecanumOdometry<T: RotarySensor> {
+    wheels: [TrackingWheel<T>; 4],
+    track_width: f64,
+    wheelbase: f64,
+    travel: [f64; 4],
+    zero: f64,
+    position: Vec2<f64>,
+    heading: Angle,
+}
+
+impl<T: RotarySensor> MecanumOdometry<T> {
+    /// Tracks from the four wheels of a mecanum drivetrain, given as front left, back left, front
+    /// right and back right. The pose starts at the origin facing +y.
+    pub fn new(wheels: [TrackingWheel<T>; 4], track_width: f64, wheelbase: f64) -> Self {
+        let mut odometry = Self {
+            wheels,
+            track_width,
+            wheelbase,
+            travel: [0.; 4],
+            zero: 0.,
+            position: Vec2 { x: 0., y: 0. },
+            heading: Angle::default(),
+        };
+        odometry.read();
+
+        odometry
+    }
+
+    /// Reads every wheel, returning how far each moved since the last read. A wheel that can't be
+    /// read is treated as not having moved.
+    fn read(&mut self) -> [f64; 4] {
+        let mut delta = [0.; 4];
+
+        for ((travel, delta), wheel) in self.travel.iter_mut().zip(&mut delta).zip(&self.wheels) {
+            if let Ok(t) = wheel.travel() {
+                *delta = t - *travel;
+                *travel = t;
+            }
+        }
+
+        delta
+    }
+
+    /// Reads the wheels and returns how the robot moved since the last update, also moving the
+    /// tracked pose.
+    pub fn update(&mut self) -> Displacement {
+        let displacement = Displacement::from_wheels(self.read(), self.track_width, self.wheelbase);
+        (self.position, self.heading) = displacement.apply(self.position, self.heading);
+
+        displacement
+    }
+
+    pub fn position(&self) -> Vec2<f64> {
+        self.position
+    }
+
+    pub fn heading(&self) -> Angle {
+        self.heading
+    }
+
+    pub fn set_pose(&mut self, position: Vec2<f64>, heading: Angle) {
+        self.position = position;
+        self.heading = heading;
+    }
+
+    /// Average travel of the wheels since they were zeroed, in meters.
+    pub fn forward_travel(&self) -> f64 {
+        self.travel.iter().sum::<f64>() / 4. - self.zero
+    }
+
+    /// Counts forward travel from where the wheels are now.
+    pub fn zero_travel(&mut self) {
+        self.zero += self.forward_travel();
+    }
+}
@@ -0,0 +1,162 @@
+// Pre-match placement guidance
+//
+// While the robot is disabled before a match, the GPS pose is compared with the start pose of the
+// route about to run, and the error is shown on the controller screen and the brain display with
+// arrows saying which way to nudge the robot.
+
+use alloc::{format, string::String};
+use core::time::Duration;
+
+use evian::prelude::*;
+use vexide::devices::{
+    controller::ControllerScreen,
+    display::{Circle, Display, Font, FontFamily, FontSize, Line, Text},
+    math::Point2,
+};
+
+use crate::{
+    odometry::{to_field_frame, to_robot_frame},
+    preview::{self, Preview},
+    start::StartError,
+    teams::{Alliance, Side},
+};
+
+// Characters across the controller screen.
+const SCREEN_WIDTH: usize = 19;
+
+/// How often guidance is refreshed. Each refresh writes one line of the controller screen.
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where a route wants the robot placed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub route: (Alliance, Side),
+    pub position: Vec2<f64>,
+    pub heading: Angle,
+}
+
+/// Which way to move the robot to reach a start pose, relative to the robot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Guidance {
+    pub error: StartError,
+    /// Meters to push the robot forward (negative is back).
+    pub forward: f64,
+    /// Meters to push the robot right (negative is left).
+    pub right: f64,
+}
+
+impl Guidance {
+    pub fn new(placement: &Placement, position: Vec2<f64>, heading: Angle) -> Self {
+        let error = to_robot_frame(placement.position - position, heading);
+
+        Self {
+            error: StartError::new((placement.position, placement.heading), (position, heading)),
+            forward: error.y,
+            right: error.x,
+        }
+    }
+
+    fn nudge(meters: f64, positive: char, negative: char) -> String {
+        let arrow = if meters >= 0. { positive } else { negative };
+        format!("{arrow}{:.0}cm", meters.abs() * 100.)
+    }
+
+    /// Three short lines for the controller screen, under `title`.
+    pub fn lines(&self, title: String) -> [String; 3] {
+        if self.error.is_within_tolerance() {
+            return [title, String::from("Placed"), String::new()];
+        }
+
+        // A positive heading error is a clockwise turn.
+        let turn = if self.error.heading >= 0. {
+            "CW"
+        } else {
+            "CCW"
+        };
+
+        [
+            title,
+            format!(
+                "{} {}",
+                Self::nudge(self.forward, '^', 'v'),
+                Self::nudge(self.right, '>', '<')
+            ),
+            format!(
+                "Turn {turn} {:.0}deg",
+                self.error.heading.abs().to_degrees()
+            ),
+        ]
+    }
+
+    /// Writes one line of guidance to the controller screen. The screen only takes one write per
+    /// update, so callers cycle `line` through 0..3.
+    pub fn show(&self, screen: &mut ControllerScreen, title: String, line: u8) {
+        let line = line % 3;
+        show_line(screen, &self.lines(title)[line as usize], line);
+    }
+
+    /// Draws the field with the start pose, the robot and the error on the brain display.
+    pub fn draw(
+        &self,
+        display: &mut Display,
+        placement: &Placement,
+        position: Vec2<f64>,
+        heading: Angle,
+    ) {
+        display.erase(preview::BACKGROUND);
+        Preview::draw_field(display);
+
+        let start_color = match placement.route.0 {
+            Alliance::Red => preview::RED,
+            Alliance::Blue => preview::BLUE,
+        };
+
+        let pose = |position: Vec2<f64>, heading: Angle| {
+            let tip = position + to_field_frame(Vec2 { x: 0., y: 0.2 }, heading);
+            (Preview::to_screen(position), Preview::to_screen(tip))
+        };
+
+        let (start, start_tip) = pose(placement.position, placement.heading);
+        display.fill(&Circle::new(start, 5), start_color);
+        display.fill(&Line::new(start, start_tip), start_color);
+
+        let (robot, robot_tip) = pose(position, heading);
+        display.fill(&Circle::new(robot, 4), preview::TRAIL);
+        display.fill(&Line::new(robot, robot_tip), preview::TRAIL);
+        display.fill(&Line::new(robot, start), preview::GOAL);
+
+        let font = Font::new(FontSize::SMALL, FontFamily::Monospace);
+        let left = preview::FIELD_LEFT + preview::FIELD_PX + 20;
+        let (alliance, side) = placement.route;
+        let [title, nudge, turn] = self.lines(format!("{alliance:?} {side:?}"));
+        let lines = [
+            format!("Placing: {title}"),
+            format!("Off {:.1} cm", self.error.distance * 100.),
+            nudge,
+            turn,
+        ];
+
+        for (i, line) in lines.iter().enumerate() {
+            display.draw_text(
+                &Text::new(
+                    line,
+                    font,
+                    Point2 {
+                        x: left,
+                        y: preview::FIELD_TOP + i as i16 * 20,
+                    },
+                ),
+                preview::TRAIL,
+                None,
+            );
+        }
+    }
+}
+
+/// Writes `text` to a controller screen line, counting from 0.
+pub fn show_line(screen: &mut ControllerScreen, text: &str, line: u8) {
+    // Padded so a shorter line covers the one before it.
+    let text = format!("{text:<SCREEN_WIDTH$}");
+
+    screen.try_set_text(text, line + 1, 1).ok();
+}
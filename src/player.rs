@@ -0,0 +1,80 @@
+// Event dispatch for replayed routes
+//
+// `play` is the one place that decides what each event does, so anything that runs a recording,
+// whether that's the real robot or the dry-run preview, behaves the same way.
+
+use evian::prelude::*;
+
+use crate::{auton::Event, battery, follower::PoseFollower, mirror::Mirror};
+
+/// Something a recording can be played on.
+pub trait Target {
+    /// Field pose, or `None` when the target doesn't know where it is on the field.
+    fn pose(&self) -> Option<(Vec2<f64>, Angle)>;
+
+    /// Drives with a robot-relative strafe/forward vector and rotation.
+    fn drive(&mut self, vector: Vec2<f64>, r: f64);
+
+    /// Runs any event that isn't a drive input, pose, battery reading or wait.
+    fn mechanism(&mut self, event: Event);
+
+    /// Waits `micros`, returning early if the target was aborted.
+    async fn wait(&mut self, micros: u64);
+
+    /// Checked between events; returning true stops playback.
+    fn aborted(&mut self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Finished,
+    Aborted,
+}
+
+fn follow(target: &mut impl Target, follower: &PoseFollower, scale: f64) {
+    let (vector, r) = follower.output(target.pose());
+
+    target.drive(
+        Vec2 {
+            x: vector.x * scale,
+            y: vector.y * scale,
+        },
+        r * scale,
+    );
+}
+
+pub async fn play(target: &mut impl Target, frames: &[Event], mirror: Mirror) -> Outcome {
+    let mut follower = PoseFollower::default();
+    // Battery compensation, updated whenever the recording notes its battery voltage.
+    let mut scale = 1.;
+
+    for &frame in frames {
+        if target.aborted() {
+            return Outcome::Aborted;
+        }
+
+        match frame {
+            Event::Input { x, y, r } => {
+                let (x, y, r) = mirror.input(x, y, r);
+                follower.set_input(x, y, r);
+                follow(target, &follower, scale);
+            }
+            Event::Pose { x, y, h } => {
+                let (position, heading) = mirror.pose(Vec2 { x, y }, Angle::from_radians(h));
+                follower.set_target(position, heading);
+                follow(target, &follower, scale);
+            }
+            Event::Battery(volts) => {
+                scale = battery::compensation(volts);
+            }
+            Event::Wait(micros) => {
+                target.wait(micros).await;
+            }
+            event => target.mechanism(event),
+        }
+    }
+
+    Outcome::Finished
+}
@@ -0,0 +1,44 @@
+// Practice replays from driver control
+//
+// During practice a route can be replayed without switching to autonomous, or played backwards to
+// bring the robot back to its starting tile. Moving a stick or pressing the abort button hands
+// control straight back to the driver.
+
+use vexide::devices::controller::ControllerState;
+
+// How far, out of 1, a stick has to move before it overrides the replay.
+const OVERRIDE_THRESHOLD: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Replay,
+    Return,
+}
+
+/// Press Up + Y together to replay the last route run or previewed, or Down + Y to drive back to
+/// where it starts.
+pub fn requested_mode(state: &ControllerState) -> Option<Mode> {
+    if !state.button_y.is_pressed() {
+        return None;
+    }
+
+    if state.button_up.is_pressed() {
+        Some(Mode::Replay)
+    } else if state.button_down.is_pressed() {
+        Some(Mode::Return)
+    } else {
+        None
+    }
+}
+
+/// Whether the driver wants the replay stopped.
+pub fn abort_requested(state: &ControllerState) -> bool {
+    let sticks = [
+        state.left_stick.x(),
+        state.left_stick.y(),
+        state.right_stick.x(),
+        state.right_stick.y(),
+    ];
+
+    state.button_b.is_pressed() || sticks.iter().any(|axis| axis.abs() > OVERRIDE_THRESHOLD)
+}
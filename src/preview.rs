@@ -0,0 +1,362 @@
+// Dry-run route preview
+//
+// Plays a route through a kinematic model of the mecanum drivetrain instead of the motors, then
+// draws the predicted path over the field on the brain display. Events go through the same
+// `player::play` dispatch as autonomous, so the preview sees exactly what the robot would do.
+
+use alloc::vec::Vec;
+
+use evian::prelude::*;
+use replay::{edit, path::Step};
+use vexide::{
+    devices::{
+        display::{Circle, Display, Font, FontFamily, FontSize, Line, Rect, Text},
+        math::Point2,
+        rgb::Rgb,
+    },
+    prelude::*,
+};
+
+use crate::{
+    EFFECTIVE_WHEELBASE, MAX_SPEED, TRACK_WIDTH,
+    auton::Event,
+    hybrid::{self, Stage},
+    mechanisms::Mechanism,
+    mirror::Mirror,
+    odometry::{Displacement, to_field_frame},
+    player::{self, Target},
+    teams::Alliance,
+};
+
+/// Longest step the model integrates at once, in microseconds.
+const STEP: u64 = 10_000;
+
+// Trail points closer than this, in meters, are merged so long routes stay cheap to draw.
+const TRAIL_RESOLUTION: f64 = 0.01;
+
+const FIELD_SIZE: f64 = 3.6576;
+const TILES: i16 = 6;
+
+// Where the field is drawn on the screen, in pixels.
+pub const FIELD_LEFT: i16 = 10;
+pub const FIELD_TOP: i16 = 10;
+pub const FIELD_PX: i16 = 216;
+
+pub const BACKGROUND: Rgb<u8> = Rgb::new(0, 0, 0);
+const TILE: Rgb<u8> = Rgb::new(60, 60, 60);
+const PERIMETER: Rgb<u8> = Rgb::new(200, 200, 200);
+pub const GOAL: Rgb<u8> = Rgb::new(230, 200, 40);
+pub const RED: Rgb<u8> = Rgb::new(220, 40, 40);
+pub const BLUE: Rgb<u8> = Rgb::new(40, 90, 230);
+pub const TRAIL: Rgb<u8> = Rgb::new(255, 255, 255);
+const INTAKE_MARKER: Rgb<u8> = Rgb::new(40, 220, 90);
+const ROUTER_MARKER: Rgb<u8> = Rgb::new(220, 120, 220);
+
+pub struct Preview {
+    position: Vec2<f64>,
+    heading: Angle,
+
+    vector: Vec2<f64>,
+    r: f64,
+
+    elapsed: u64,
+    trail: Vec<Vec2<f64>>,
+    markers: Vec<(Vec2<f64>, Mechanism)>,
+}
+
+impl Preview {
+    /// Starts the model at the route's start pose, or at the field center if the recording has
+    /// no poses.
+    pub fn new(start: Option<(Vec2<f64>, Angle)>) -> Self {
+        let (position, heading) = start.unwrap_or((Vec2 { x: 0., y: 0. }, Angle::from_radians(0.)));
+
+        Self {
+            position,
+            heading,
+            vector: Vec2 { x: 0., y: 0. },
+            r: 0.,
+            elapsed: 0,
+            trail: alloc::vec![position],
+            markers: Vec::new(),
+        }
+    }
+
+    /// Previews a path-following route, which drives straight between its waypoints.
+    pub fn trace_path(&mut self, steps: &[Step], mirror: Mirror) {
+        for &step in steps {
+            match step {
+                Step::MoveTo(waypoint) => {
+                    let (position, heading) = mirror.pose(
+                        Vec2 {
+                            x: waypoint.x,
+                            y: waypoint.y,
+                        },
+                        Angle::from_radians(waypoint.h),
+                    );
+
+                    self.position = position;
+                    self.heading = heading;
+                    self.trail.push(position);
+                }
+                Step::Event(event) => self.mechanism(event),
+            }
+        }
+    }
+
+    /// Previews a hybrid route. Motion-controlled moves jump straight to their pose, and waits
+    /// assume the drivetrain is already stopped.
+    pub async fn trace_hybrid(&mut self, events: &[Event], stages: &[Stage], mirror: Mirror) {
+        let start = self.elapsed;
+
+        for &stage in stages {
+            match stage {
+                Stage::Replay { from, to } => {
+                    player::play(self, &edit::slice(events, from, to), mirror).await;
+                    self.drive(Vec2 { x: 0., y: 0. }, 0.);
+                }
+                Stage::MoveTo { at } => {
+                    if let Some(waypoint) = hybrid::pose_at(events, at) {
+                        self.trace_path(&[Step::MoveTo(waypoint)], mirror);
+                    }
+                }
+                Stage::TurnTo { heading } => {
+                    self.heading = mirror.heading(Angle::from_degrees(heading));
+                }
+                // The model has no tracking error to reset.
+                Stage::Assume { .. } => {}
+                Stage::Mechanism(event) => self.mechanism(event),
+                Stage::WaitUntil { condition, timeout } => {
+                    let mut waited = 0;
+
+                    while waited < timeout && !condition.holds(self.elapsed - start, 0., 0.) {
+                        self.wait(STEP).await;
+                        waited += STEP;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Advances the model by `micros` at the current input.
+    fn integrate(&mut self, micros: u64) {
+        // Wheel powers as `Mecanum::drive_vector` computes them, including its normalization.
+        let Vec2 { x, y } = self.vector;
+        let wheels = [
+            y + x + self.r,
+            y - x + self.r,
+            y - x - self.r,
+            y + x - self.r,
+        ];
+        let max = wheels.iter().fold(1., |max: f64, w| max.max(w.abs()));
+        let dt = micros as f64 / 1_000_000.;
+        let travel = wheels.map(|w| w / max * MAX_SPEED * dt);
+
+        (self.position, self.heading) =
+            Displacement::from_wheels(travel, TRACK_WIDTH, EFFECTIVE_WHEELBASE)
+                .apply(self.position, self.heading);
+
+        if self
+            .trail
+            .last()
+            .is_none_or(|last| last.distance(self.position) >= TRAIL_RESOLUTION)
+        {
+            self.trail.push(self.position);
+        }
+    }
+
+    /// Screen coordinates of a field position.
+    pub fn to_screen(position: Vec2<f64>) -> Point2<i16> {
+        let scale = FIELD_PX as f64 / FIELD_SIZE;
+
+        Point2 {
+            x: FIELD_LEFT + ((position.x + FIELD_SIZE / 2.) * scale) as i16,
+            y: FIELD_TOP + ((FIELD_SIZE / 2. - position.y) * scale) as i16,
+        }
+    }
+
+    pub fn draw_field(display: &mut Display) {
+        let tile = FIELD_PX / TILES;
+
+        for i in 1..TILES {
+            let offset = i * tile;
+
+            display.fill(
+                &Line::new(
+                    Point2 {
+                        x: FIELD_LEFT + offset,
+                        y: FIELD_TOP,
+                    },
+                    Point2 {
+                        x: FIELD_LEFT + offset,
+                        y: FIELD_TOP + FIELD_PX,
+                    },
+                ),
+                TILE,
+            );
+            display.fill(
+                &Line::new(
+                    Point2 {
+                        x: FIELD_LEFT,
+                        y: FIELD_TOP + offset,
+                    },
+                    Point2 {
+                        x: FIELD_LEFT + FIELD_PX,
+                        y: FIELD_TOP + offset,
+                    },
+                ),
+                TILE,
+            );
+        }
+
+        // Alliance walls: red along -x, blue along +x.
+        display.fill(
+            &Rect::new(
+                Point2 {
+                    x: FIELD_LEFT - 4,
+                    y: FIELD_TOP,
+                },
+                Point2 {
+                    x: FIELD_LEFT - 1,
+                    y: FIELD_TOP + FIELD_PX,
+                },
+            ),
+            RED,
+        );
+        display.fill(
+            &Rect::new(
+                Point2 {
+                    x: FIELD_LEFT + FIELD_PX + 1,
+                    y: FIELD_TOP,
+                },
+                Point2 {
+                    x: FIELD_LEFT + FIELD_PX + 4,
+                    y: FIELD_TOP + FIELD_PX,
+                },
+            ),
+            BLUE,
+        );
+
+        display.stroke(
+            &Rect::new(
+                Point2 {
+                    x: FIELD_LEFT,
+                    y: FIELD_TOP,
+                },
+                Point2 {
+                    x: FIELD_LEFT + FIELD_PX,
+                    y: FIELD_TOP + FIELD_PX,
+                },
+            ),
+            PERIMETER,
+        );
+
+        // The two center goals cross at the middle of the field.
+        let reach = FIELD_SIZE / TILES as f64 * 0.6;
+        for (dx, dy) in [(reach, reach), (reach, -reach)] {
+            display.fill(
+                &Line::new(
+                    Self::to_screen(Vec2 { x: -dx, y: -dy }),
+                    Self::to_screen(Vec2 { x: dx, y: dy }),
+                ),
+                GOAL,
+            );
+        }
+    }
+
+    /// Draws the field and the predicted path, replacing whatever was on the screen.
+    pub fn draw(&self, display: &mut Display, alliance: Alliance) {
+        display.erase(BACKGROUND);
+        Self::draw_field(display);
+
+        for pair in self.trail.windows(2) {
+            display.fill(
+                &Line::new(Self::to_screen(pair[0]), Self::to_screen(pair[1])),
+                TRAIL,
+            );
+        }
+
+        for &(position, mechanism) in &self.markers {
+            let color = match mechanism {
+                Mechanism::Intake => INTAKE_MARKER,
+                Mechanism::Router => ROUTER_MARKER,
+            };
+
+            display.fill(&Circle::new(Self::to_screen(position), 3), color);
+        }
+
+        let start_color = match alliance {
+            Alliance::Red => RED,
+            Alliance::Blue => BLUE,
+        };
+        if let Some(&start) = self.trail.first() {
+            display.fill(&Circle::new(Self::to_screen(start), 5), start_color);
+        }
+
+        // Arrow from the final position along the final heading.
+        let end = Self::to_screen(self.position);
+        let tip =
+            Self::to_screen(self.position + to_field_frame(Vec2 { x: 0., y: 0.2 }, self.heading));
+        display.fill(&Circle::new(end, 4), TRAIL);
+        display.fill(&Line::new(end, tip), TRAIL);
+
+        let font = Font::new(FontSize::SMALL, FontFamily::Monospace);
+        let left = FIELD_LEFT + FIELD_PX + 20;
+        let lines = [
+            alloc::format!("Preview: {alliance:?}"),
+            alloc::format!("Duration {:.1} s", self.elapsed as f64 / 1_000_000.),
+            alloc::format!("End ({:.2}, {:.2}) m", self.position.x, self.position.y),
+            alloc::format!("Heading {:.0} deg", self.heading.as_degrees()),
+            alloc::format!("Mechanism events {}", self.markers.len()),
+        ];
+
+        for (i, line) in lines.iter().enumerate() {
+            display.draw_text(
+                &Text::new(
+                    line,
+                    font,
+                    Point2 {
+                        x: left,
+                        y: FIELD_TOP + i as i16 * 20,
+                    },
+                ),
+                TRAIL,
+                None,
+            );
+        }
+    }
+}
+
+impl Target for Preview {
+    fn pose(&self) -> Option<(Vec2<f64>, Angle)> {
+        Some((self.position, self.heading))
+    }
+
+    fn drive(&mut self, vector: Vec2<f64>, r: f64) {
+        self.vector = vector;
+        self.r = r;
+    }
+
+    fn mechanism(&mut self, event: Event) {
+        let mechanism = match event {
+            Event::IntakeFwd | Event::IntakeRev | Event::IntakeDisable => Mechanism::Intake,
+            Event::RouterFwd | Event::RouterRev | Event::RouterDisable => Mechanism::Router,
+            Event::SetVoltage(mechanism, _) => mechanism,
+            Event::Input { .. } | Event::Wait(_) | Event::Pose { .. } | Event::Battery(_) => {
+                return;
+            }
+        };
+
+        self.markers.push((self.position, mechanism));
+    }
+
+    async fn wait(&mut self, micros: u64) {
+        self.elapsed += micros;
+
+        let mut remaining = micros;
+        while remaining > 0 {
+            let step = remaining.min(STEP);
+            self.integrate(step);
+            remaining -= step;
+        }
+    }
+}
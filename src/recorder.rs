@@ -0,0 +1,201 @@
+// On-robot route recorder
+//
+// Captures the driver's inputs as `auton::Event`s so a route driven on the practice field can be
+// replayed in autonomous. Recordings are written to the SD card in the `recording` file format.
+
+use alloc::vec::Vec;
+
+use evian::prelude::*;
+use replay::BATTERY_RESOLUTION;
+use vexide::{io, time::Instant};
+
+use crate::{auton::Event, mechanisms::Mechanism, recording};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum MechanismState {
+    Forward,
+    Reverse,
+    #[default]
+    Disabled,
+}
+
+impl MechanismState {
+    pub fn from_buttons(forward: bool, reverse: bool) -> Self {
+        if forward && !reverse {
+            Self::Forward
+        } else if reverse && !forward {
+            Self::Reverse
+        } else {
+            Self::Disabled
+        }
+    }
+
+    fn event(self, mechanism: Mechanism) -> Event {
+        match (mechanism, self) {
+            (Mechanism::Intake, Self::Forward) => Event::IntakeFwd,
+            (Mechanism::Intake, Self::Reverse) => Event::IntakeRev,
+            (Mechanism::Intake, Self::Disabled) => Event::IntakeDisable,
+            (Mechanism::Router, Self::Forward) => Event::RouterFwd,
+            (Mechanism::Router, Self::Reverse) => Event::RouterRev,
+            (Mechanism::Router, Self::Disabled) => Event::RouterDisable,
+        }
+    }
+}
+
+/// Driver inputs and robot pose for a single controller tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub input: (f64, f64, f64),
+    pub intake: MechanismState,
+    pub router: MechanismState,
+    pub position: Vec2<f64>,
+    pub heading: Angle,
+    pub battery: f64,
+}
+
+pub struct Recording {
+    events: Vec<Event>,
+    last_event: Instant,
+    input: Option<(f64, f64, f64)>,
+    intake: Option<MechanismState>,
+    router: Option<MechanismState>,
+    pose: Option<(Vec2<f64>, Angle)>,
+    battery: Option<f64>,
+}
+
+impl Recording {
+    fn new() -> Self {
+        Self {
+            events: Vec::new(),
+            last_event: Instant::now(),
+            input: None,
+            intake: None,
+            router: None,
+            pose: None,
+            battery: None,
+        }
+    }
+
+    /// Inserts a wait covering the time since the last recorded event.
+    fn mark_time(&mut self) {
+        let now = Instant::now();
+
+        if !self.events.is_empty() {
+            let micros = now.duration_since(self.last_event).as_micros() as u64;
+
+            if micros > 0 {
+                self.events.push(Event::Wait(micros));
+            }
+        }
+
+        self.last_event = now;
+    }
+
+    pub fn capture(&mut self, sample: Sample) {
+        let input_changed = self.input != Some(sample.input);
+        let intake_changed = self.intake != Some(sample.intake);
+        let router_changed = self.router != Some(sample.router);
+        let pose_changed = self.pose != Some((sample.position, sample.heading));
+        let battery_changed = self
+            .battery
+            .is_none_or(|battery| (battery - sample.battery).abs() >= BATTERY_RESOLUTION);
+
+        if !input_changed && !intake_changed && !router_changed && !pose_changed && !battery_changed
+        {
+            return;
+        }
+
+        self.mark_time();
+
+        if battery_changed {
+            self.events.push(Event::Battery(sample.battery));
+            self.battery = Some(sample.battery);
+        }
+
+        if pose_changed {
+            self.events.push(Event::Pose {
+                x: sample.position.x,
+                y: sample.position.y,
+                h: sample.heading.as_radians(),
+            });
+            self.pose = Some((sample.position, sample.heading));
+        }
+
+        if input_changed {
+            let (x, y, r) = sample.input;
+            self.events.push(Event::Input { x, y, r });
+            self.input = Some(sample.input);
+        }
+
+        if intake_changed {
+            self.events.push(sample.intake.event(Mechanism::Intake));
+            self.intake = Some(sample.intake);
+        }
+
+        if router_changed {
+            self.events.push(sample.router.event(Mechanism::Router));
+            self.router = Some(sample.router);
+        }
+    }
+
+    /// Ends the recording with the drivetrain stopped and every mechanism disabled, matching the
+    /// tail of the compiled routes.
+    fn finish(mut self) -> Vec<Event> {
+        self.mark_time();
+        self.events.push(Event::Input {
+            x: 0.,
+            y: 0.,
+            r: 0.,
+        });
+        self.events.push(Event::IntakeDisable);
+        self.events.push(Event::RouterDisable);
+
+        self.events
+    }
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    /// Where the current recording will be saved.
+    path: &'static str,
+    recording: Option<Recording>,
+    combo_held: bool,
+}
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Toggles recording on the rising edge of the record combo. A recording started now will be
+    /// saved to `path`. Returns the path of the saved recording when one was just stopped.
+    pub fn update_combo(
+        &mut self,
+        combo: bool,
+        path: &'static str,
+    ) -> Option<io::Result<&'static str>> {
+        let pressed = combo && !self.combo_held;
+        self.combo_held = combo;
+
+        if !pressed {
+            return None;
+        }
+
+        match self.recording.take() {
+            Some(recording) => {
+                Some(recording::save(self.path, &recording.finish()).map(|_| self.path))
+            }
+            None => {
+                self.path = path;
+                self.recording = Some(Recording::new());
+                None
+            }
+        }
+    }
+
+    pub fn capture(&mut self, sample: Sample) {
+        if let Some(recording) = &mut self.recording {
+            recording.capture(sample);
+        }
+    }
+}
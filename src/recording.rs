@@ -0,0 +1,35 @@
+// Recordings on the SD card. They're saved in the compact format, and either format can be loaded.
+
+use alloc::vec::Vec;
+
+use replay::{
+    compact,
+    format::{self, DecodeError},
+};
+use vexide::{
+    fs::{self, File},
+    io::{self, Read},
+};
+
+use crate::auton::Event;
+
+pub type LoadError = DecodeError<io::Error>;
+
+/// Streams a recording off the SD card rather than reading the whole file up front.
+struct SdFile(File);
+
+impl format::Read for SdFile {
+    type Error = io::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+}
+
+pub fn save(path: &str, events: &[Event]) -> io::Result<()> {
+    fs::write(path, compact::encode(events))
+}
+
+pub fn load(path: &str) -> Result<Vec<Event>, LoadError> {
+    format::decode(SdFile(File::open(path).map_err(DecodeError::Read)?))
+}
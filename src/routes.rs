@@ -0,0 +1,274 @@
+// Autonomous route registry
+//
+// Every starting tile has its own list of sources, tried in order until one loads. A source that
+// was recorded from a different tile is mirrored onto the tile being run. Sources are either
+// replayed as recorded, converted to a path and driven with motion control, or run as a hybrid of
+// the two. Each tile also decides what happens when the robot isn't placed where its route
+// starts.
+
+use alloc::borrow::Cow;
+
+use evian::prelude::*;
+use replay::validate::{self, AUTONOMOUS_PERIOD};
+use vexide::prelude::println;
+
+use crate::{
+    auton::{Event, FRAMES},
+    hybrid::{Condition, Stage},
+    mirror::Mirror,
+    recording,
+    start::{self, StartPolicy},
+    teams::{Alliance, Side},
+};
+
+#[derive(Clone, Copy)]
+pub enum Content {
+    /// Recording on the SD card.
+    Recording(&'static str),
+    /// Event script compiled into the program.
+    Script(&'static [Event]),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Playback {
+    /// Replay the recorded inputs, correcting toward recorded poses.
+    Replay,
+    /// Simplify the recorded poses into waypoints, allowing the path to stray `tolerance` meters
+    /// from the recording, and drive them with motion control.
+    Path { tolerance: f64 },
+    /// Run the stages in order against the recording.
+    Hybrid(&'static [Stage]),
+}
+
+#[derive(Clone, Copy)]
+pub struct Source {
+    pub content: Content,
+    pub recorded_from: (Alliance, Side),
+    pub playback: Playback,
+}
+
+impl Source {
+    const fn recording(path: &'static str, recorded_from: (Alliance, Side)) -> Self {
+        Self {
+            content: Content::Recording(path),
+            recorded_from,
+            playback: Playback::Replay,
+        }
+    }
+
+    const fn path(path: &'static str, recorded_from: (Alliance, Side)) -> Self {
+        Self {
+            content: Content::Recording(path),
+            recorded_from,
+            playback: Playback::Path {
+                tolerance: PATH_TOLERANCE,
+            },
+        }
+    }
+
+    const fn hybrid(
+        path: &'static str,
+        recorded_from: (Alliance, Side),
+        stages: &'static [Stage],
+    ) -> Self {
+        Self {
+            content: Content::Recording(path),
+            recorded_from,
+            playback: Playback::Hybrid(stages),
+        }
+    }
+
+    const fn script(
+        events: &'static [Event],
+        recorded_from: (Alliance, Side),
+        stages: &'static [Stage],
+    ) -> Self {
+        Self {
+            content: Content::Script(events),
+            recorded_from,
+            playback: Playback::Hybrid(stages),
+        }
+    }
+
+    fn load(&self) -> Option<Cow<'static, [Event]>> {
+        let events = match self.content {
+            Content::Recording(path) => match recording::load(path) {
+                Ok(events) => Some(Cow::Owned(events)),
+                Err(err) => {
+                    println!("Couldn't load {path}: {err:?}");
+                    None
+                }
+            },
+            Content::Script(events) => Some(Cow::Borrowed(events)),
+        }?;
+
+        let report = validate::validate(&events, AUTONOMOUS_PERIOD);
+        if !report.is_ok() {
+            println!(
+                "Route runs {}us and ends with {:?}: {:?}",
+                report.duration, report.final_state, report.issues
+            );
+        }
+
+        Some(events)
+    }
+}
+
+pub struct Plan {
+    pub events: Cow<'static, [Event]>,
+    /// Tile the events were recorded from.
+    pub recorded_from: (Alliance, Side),
+    /// Transform from where the events were recorded to the tile being run.
+    pub mirror: Mirror,
+    pub playback: Playback,
+    pub start_policy: StartPolicy,
+}
+
+impl Plan {
+    /// Pose the route expects the robot to start from, if the recording has poses.
+    pub fn start_pose(&self) -> Option<(Vec2<f64>, Angle)> {
+        start::start_pose(&self.events, self.mirror)
+    }
+}
+
+pub struct Route {
+    pub start: (Alliance, Side),
+    pub sources: &'static [Source],
+    pub start_policy: StartPolicy,
+}
+
+impl Route {
+    /// Loads the first available source.
+    pub fn load(&self) -> Option<Plan> {
+        self.sources.iter().find_map(|source| {
+            source.load().map(|events| Plan {
+                events,
+                recorded_from: source.recorded_from,
+                mirror: Mirror::new(source.recorded_from, self.start),
+                playback: source.playback,
+                start_policy: self.start_policy,
+            })
+        })
+    }
+
+    /// The tile's own replayed recording, which the recorder saves to when driving from it.
+    pub fn recording_path(&self) -> Option<&'static str> {
+        self.sources
+            .iter()
+            .find_map(|source| match (source.content, source.playback) {
+                (Content::Recording(path), Playback::Replay)
+                    if source.recorded_from == self.start =>
+                {
+                    Some(path)
+                }
+                _ => None,
+            })
+    }
+}
+
+// Recording written by the driver-control recorder when it can't tell which tile it started
+// from. No route plays it; copy it to a tile's file once it's known.
+pub const RECORDER_PATH: &str = "auton.pbr";
+
+// How far, in meters, a path may stray from the recording it was simplified from.
+const PATH_TOLERANCE: f64 = 0.05;
+
+// Replays the opening and middle of the route freehand, squaring up on the recorded poses before
+// each, and holds the intake in reverse to score until the period is nearly over. The robot starts
+// squared against the field wall, so tracking takes the recorded start heading and keeps the GPS
+// from correcting it through the opening.
+const HYBRID: [Stage; 9] = [
+    Stage::Assume {
+        at: 0,
+        position: false,
+        heading: true,
+        hold: 4_000_000,
+    },
+    Stage::Replay {
+        from: 0,
+        to: 4_000_000,
+    },
+    Stage::WaitUntil {
+        condition: Condition::Stopped,
+        timeout: 500_000,
+    },
+    Stage::MoveTo { at: 4_000_000 },
+    Stage::Replay {
+        from: 4_000_000,
+        to: 11_000_000,
+    },
+    Stage::MoveTo { at: 11_000_000 },
+    Stage::Mechanism(Event::IntakeRev),
+    Stage::WaitUntil {
+        condition: Condition::Elapsed(14_500_000),
+        timeout: 3_000_000,
+    },
+    Stage::Mechanism(Event::IntakeDisable),
+];
+
+// The compiled script expects the robot to have turned to face 100 degrees before it starts.
+const SCRIPT: [Stage; 2] = [
+    Stage::TurnTo { heading: 100. },
+    Stage::Replay {
+        from: 0,
+        to: u64::MAX,
+    },
+];
+
+const RED_RIGHT: (Alliance, Side) = (Alliance::Red, Side::Right);
+const RED_LEFT: (Alliance, Side) = (Alliance::Red, Side::Left);
+const BLUE_RIGHT: (Alliance, Side) = (Alliance::Blue, Side::Right);
+const BLUE_LEFT: (Alliance, Side) = (Alliance::Blue, Side::Left);
+
+// What every tile does when the robot isn't on its route's start. A misplaced robot still scores
+// more by running than by sitting out the period, and replay already corrects toward the recorded
+// poses once it's moving. Driving onto the start first would stake the whole route on one GPS
+// reading taken against the wall, before the robot has moved.
+const MATCH_POLICY: StartPolicy = StartPolicy::Warn;
+
+// Compiled route every tile falls back to.
+const SHARED: Source = Source::script(&FRAMES, RED_RIGHT, &SCRIPT);
+
+pub static ROUTES: [Route; 4] = [
+    Route {
+        start: RED_RIGHT,
+        sources: &[
+            Source::hybrid("red_right_hybrid.pbr", RED_RIGHT, &HYBRID),
+            Source::path("red_right_path.pbr", RED_RIGHT),
+            Source::recording("red_right.pbr", RED_RIGHT),
+            SHARED,
+        ],
+        start_policy: MATCH_POLICY,
+    },
+    Route {
+        start: RED_LEFT,
+        sources: &[
+            Source::path("red_left_path.pbr", RED_LEFT),
+            Source::recording("red_left.pbr", RED_LEFT),
+            SHARED,
+        ],
+        start_policy: MATCH_POLICY,
+    },
+    Route {
+        start: BLUE_RIGHT,
+        sources: &[
+            Source::path("blue_right_path.pbr", BLUE_RIGHT),
+            Source::recording("blue_right.pbr", BLUE_RIGHT),
+            SHARED,
+        ],
+        start_policy: MATCH_POLICY,
+    },
+    Route {
+        start: BLUE_LEFT,
+        sources: &[
+            Source::path("blue_left_path.pbr", BLUE_LEFT),
+            Source::recording("blue_left.pbr", BLUE_LEFT),
+            SHARED,
+        ],
+        start_policy: MATCH_POLICY,
+    },
+];
+
+pub fn find(alliance: Alliance, side: Side) -> Option<&'static Route> {
+    ROUTES.iter().find(|route| route.start == (alliance, side))
+}
@@ -0,0 +1,177 @@
+// Autonomous selector
+//
+// Lists the autonomous choices as rows on the brain display and selects a row when it's tapped.
+// The robot owns both the selector and the display, so it can read the choice back and hand the
+// screen to placement guidance or a preview without them drawing over each other.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use vexide::devices::{
+    display::{Display, Font, FontFamily, FontSize, Rect, Text, TouchState},
+    math::Point2,
+    rgb::Rgb,
+};
+
+use crate::{
+    preview,
+    routes::Route,
+    teams::{Alliance, Side},
+};
+
+// Where the list is drawn on the screen, in pixels.
+const LEFT: i16 = 10;
+const TOP: i16 = 10;
+const WIDTH: i16 = 460;
+const ROW_HEIGHT: i16 = 24;
+
+const ROW: Rgb<u8> = Rgb::new(40, 40, 40);
+const SELECTED: Rgb<u8> = Rgb::new(40, 140, 70);
+
+/// What autonomous does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    /// Runs the route for the tile the GPS puts the robot on.
+    Auto,
+    Run(Alliance, Side),
+    /// Draws the route's predicted path instead of driving it.
+    Preview(Alliance, Side),
+}
+
+struct Entry {
+    label: String,
+    choice: Choice,
+}
+
+pub struct Selector {
+    entries: Vec<Entry>,
+    selected: usize,
+    /// Whether the screen was being touched at the last update.
+    touched: bool,
+}
+
+impl Selector {
+    /// Lists `Auto (GPS)`, then an entry to run and one to preview each route that has something
+    /// to run. A route that would fall back to a recording from another tile says which one it's
+    /// mirrored from.
+    ///
+    /// The first route starts selected rather than `Auto (GPS)`, so a match where the GPS can't
+    /// see the field still runs something.
+    pub fn new(routes: &[Route]) -> Self {
+        let routes: Vec<_> = routes
+            .iter()
+            .filter_map(|route| {
+                let plan = route.load()?;
+
+                let (alliance, side) = route.start;
+                let name = format!("{alliance:?} ({side:?})");
+                let label = if plan.recorded_from == route.start {
+                    name
+                } else {
+                    let (alliance, side) = plan.recorded_from;
+                    format!("{name}, mirrored from {alliance:?} ({side:?})")
+                };
+
+                Some((route.start, label))
+            })
+            .collect();
+
+        let mut entries = vec![Entry {
+            label: String::from("Auto (GPS)"),
+            choice: Choice::Auto,
+        }];
+        entries.extend(routes.iter().map(|((alliance, side), label)| Entry {
+            label: label.clone(),
+            choice: Choice::Run(*alliance, *side),
+        }));
+        entries.extend(routes.iter().map(|((alliance, side), label)| Entry {
+            label: format!("Preview: {label}"),
+            choice: Choice::Preview(*alliance, *side),
+        }));
+
+        let selected = entries
+            .iter()
+            .position(|entry| matches!(entry.choice, Choice::Run(..)))
+            .unwrap_or(0);
+
+        Self {
+            entries,
+            selected,
+            touched: false,
+        }
+    }
+
+    pub fn choice(&self) -> Choice {
+        self.entries[self.selected].choice
+    }
+
+    /// Selects the first entry for `choice`, if there is one. Returns whether the selection
+    /// changed.
+    pub fn select(&mut self, choice: Choice) -> bool {
+        match self.entries.iter().position(|entry| entry.choice == choice) {
+            Some(row) if row != self.selected => {
+                self.selected = row;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Selects the row under a new touch. Returns whether the selection changed.
+    pub fn update(&mut self, display: &Display) -> bool {
+        let touch = display.touch_status();
+        let touched = matches!(touch.state, TouchState::Pressed | TouchState::Held);
+
+        // Only the start of a touch counts, so a finger dragged across the list doesn't reselect.
+        let started = touched && !self.touched;
+        self.touched = touched;
+        if !started {
+            return false;
+        }
+
+        let Point2 { x, y } = touch.point;
+        if !(LEFT..LEFT + WIDTH).contains(&x) || y < TOP {
+            return false;
+        }
+
+        let row = ((y - TOP) / ROW_HEIGHT) as usize;
+        if row >= self.entries.len() || row == self.selected {
+            return false;
+        }
+
+        self.selected = row;
+        true
+    }
+
+    pub fn draw(&self, display: &mut Display) {
+        display.erase(preview::BACKGROUND);
+
+        let font = Font::new(FontSize::SMALL, FontFamily::Monospace);
+        for (i, entry) in self.entries.iter().enumerate() {
+            let top = TOP + i as i16 * ROW_HEIGHT;
+            let color = if i == self.selected { SELECTED } else { ROW };
+
+            display.fill(
+                &Rect::new(
+                    Point2 { x: LEFT, y: top },
+                    Point2 {
+                        x: LEFT + WIDTH,
+                        y: top + ROW_HEIGHT - 2,
+                    },
+                ),
+                color,
+            );
+            display.draw_text(
+                &Text::new(
+                    &entry.label,
+                    font,
+                    Point2 {
+                        x: LEFT + 8,
+                        y: top + 4,
+                    },
+                ),
+                preview::TRAIL,
+                None,
+            );
+        }
+    }
+}
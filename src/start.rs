@@ -0,0 +1,57 @@
+// Start-pose verification
+//
+// Replay assumes the robot starts exactly where it did while recording. Every recording notes the
+// pose it started from as its first `Event::Pose`, so before a route runs that pose is compared
+// with where the GPS says the robot is, and the route's policy decides what to do about a
+// mismatch.
+
+use evian::prelude::*;
+use replay::wrap;
+
+use crate::{auton::Event, mirror::Mirror};
+
+// Largest start error that is treated as placed correctly.
+const POSITION_TOLERANCE: f64 = 0.05;
+const HEADING_TOLERANCE: f64 = f64::to_radians(5.);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartPolicy {
+    /// Don't run the route at all.
+    #[allow(dead_code, reason = "no tile refuses, see `routes::MATCH_POLICY`")]
+    Refuse,
+    /// Run the route anyway, showing the error on the controller screen.
+    Warn,
+    /// Drive to the start pose with motion control before running the route.
+    #[allow(dead_code, reason = "no tile aligns, see `routes::MATCH_POLICY`")]
+    Align,
+}
+
+/// How far the robot is from where a route starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StartError {
+    /// Meters.
+    pub distance: f64,
+    /// Radians, wrapped to [-PI, PI].
+    pub heading: f64,
+}
+
+impl StartError {
+    pub fn new(expected: (Vec2<f64>, Angle), actual: (Vec2<f64>, Angle)) -> Self {
+        Self {
+            distance: expected.0.distance(actual.0),
+            heading: wrap(expected.1.as_radians() - actual.1.as_radians()),
+        }
+    }
+
+    pub fn is_within_tolerance(&self) -> bool {
+        self.distance <= POSITION_TOLERANCE && self.heading.abs() <= HEADING_TOLERANCE
+    }
+}
+
+/// Pose a recording starts from, mirrored onto the tile being run.
+pub fn start_pose(events: &[Event], mirror: Mirror) -> Option<(Vec2<f64>, Angle)> {
+    events.iter().find_map(|event| match *event {
+        Event::Pose { x, y, h } => Some(mirror.pose(Vec2 { x, y }, Angle::from_radians(h))),
+        _ => None,
+    })
+}
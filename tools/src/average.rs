@@ -0,0 +1,400 @@
+//! Averaging several runs of a route into one canonical recording.
+//!
+//! Each run is sampled onto a fixed grid, then time-aligned to a reference run with dynamic time
+//! warping on its pose trace, or its stick inputs if any run has no poses. The reference is the
+//! run closest to all the others. Aligned samples are averaged frame by frame, and how far the
+//! runs spread around the average is reported for each second of the route.
+
+use replay::{BATTERY_RESOLUTION, Event, Mechanism, STICK_STEPS, edit, validate::Command, wrap};
+
+/// Spacing of the sampling grid, in microseconds. Close to how often the recorder captures.
+const SAMPLE_INTERVAL: u64 = 25_000;
+
+/// Length of each segment in the variance report, in microseconds.
+const SEGMENT: u64 = 1_000_000;
+
+/// Meters of position error that count as much as one radian of heading error when aligning.
+const HEADING_WEIGHT: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Pose,
+    Input,
+}
+
+/// Everything the robot was doing at one point on the grid.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+struct Frame {
+    time: u64,
+    input: [f64; 3],
+    pose: Option<[f64; 3]>,
+    battery: Option<f64>,
+    intake: Command,
+    router: Command,
+}
+
+impl Frame {
+    fn key(&self, key: Key) -> [f64; 3] {
+        match key {
+            Key::Pose => {
+                let [x, y, h] = self.pose.unwrap_or_default();
+                [x, y, h * HEADING_WEIGHT]
+            }
+            Key::Input => self.input,
+        }
+    }
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Samples a recording onto the grid, holding each value until it next changes.
+fn sample(events: &[Event]) -> Vec<Frame> {
+    let (timeline, duration) = edit::timeline(events);
+    let mut frames = Vec::new();
+    let mut frame = Frame::default();
+    let mut timeline = timeline.iter().peekable();
+
+    let mut time = 0;
+    loop {
+        while let Some(timed) = timeline.next_if(|timed| timed.time <= time) {
+            match timed.event {
+                Event::Input { x, y, r } => frame.input = [x, y, r],
+                Event::Pose { x, y, h } => frame.pose = Some([x, y, h]),
+                Event::Battery(volts) => frame.battery = Some(volts),
+                Event::IntakeFwd => frame.intake = Command::Forward,
+                Event::IntakeRev => frame.intake = Command::Reverse,
+                Event::IntakeDisable => frame.intake = Command::Disabled,
+                Event::RouterFwd => frame.router = Command::Forward,
+                Event::RouterRev => frame.router = Command::Reverse,
+                Event::RouterDisable => frame.router = Command::Disabled,
+                Event::SetVoltage(Mechanism::Intake, volts) => {
+                    frame.intake = Command::Voltage(volts)
+                }
+                Event::SetVoltage(Mechanism::Router, volts) => {
+                    frame.router = Command::Voltage(volts)
+                }
+                Event::Wait(_) => {}
+            }
+        }
+
+        frames.push(Frame { time, ..frame });
+
+        if time >= duration {
+            break;
+        }
+
+        time = (time + SAMPLE_INTERVAL).min(duration);
+    }
+
+    frames
+}
+
+/// Aligns `b` to `a`, returning every matched pair of frame indices in order, and the total
+/// distance between matched frames.
+fn dtw(a: &[[f64; 3]], b: &[[f64; 3]]) -> (Vec<(usize, usize)>, f64) {
+    let (n, m) = (a.len(), b.len());
+    let mut cost = vec![f64::INFINITY; n * m];
+
+    for i in 0..n {
+        for j in 0..m {
+            let best = match (i, j) {
+                (0, 0) => 0.,
+                (0, _) => cost[j - 1],
+                (_, 0) => cost[(i - 1) * m],
+                _ => cost[(i - 1) * m + j - 1]
+                    .min(cost[(i - 1) * m + j])
+                    .min(cost[i * m + j - 1]),
+            };
+
+            cost[i * m + j] = best + distance(a[i], b[j]);
+        }
+    }
+
+    let mut path = vec![(n - 1, m - 1)];
+    let (mut i, mut j) = (n - 1, m - 1);
+
+    while i > 0 || j > 0 {
+        (i, j) = match (i, j) {
+            (0, _) => (0, j - 1),
+            (_, 0) => (i - 1, 0),
+            _ => [(i - 1, j - 1), (i - 1, j), (i, j - 1)]
+                .into_iter()
+                .min_by(|&(a, b), &(c, d)| cost[a * m + b].total_cmp(&cost[c * m + d]))
+                .unwrap(),
+        };
+        path.push((i, j));
+    }
+
+    path.reverse();
+
+    (path, cost[n * m - 1])
+}
+
+/// The most common command, preferring the earliest on a tie.
+fn vote(commands: &[Command]) -> Command {
+    commands
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by_key(|&(i, command)| {
+            let count = commands.iter().filter(|&&other| other == command).count();
+            (count, std::cmp::Reverse(i))
+        })
+        .map(|(_, command)| command)
+        .unwrap_or_default()
+}
+
+/// How much the runs disagreed over one segment of the canonical recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spread {
+    pub from: u64,
+    pub to: u64,
+    /// RMS distance of the runs from the average position, in meters, if they have poses.
+    pub position: Option<f64>,
+    /// RMS heading difference, in radians, if the runs have poses.
+    pub heading: Option<f64>,
+    /// RMS difference in stick inputs.
+    pub input: f64,
+}
+
+pub struct Average {
+    pub events: Vec<Event>,
+    pub key: Key,
+    /// Index of the run the others were aligned to.
+    pub reference: usize,
+    pub spread: Vec<Spread>,
+}
+
+/// Averages runs of the same route. Needs at least two runs.
+pub fn average(runs: &[Vec<Event>]) -> Average {
+    let runs: Vec<Vec<Frame>> = runs.iter().map(|run| sample(run)).collect();
+
+    let key = if runs
+        .iter()
+        .all(|run| run.iter().any(|frame| frame.pose.is_some()))
+    {
+        Key::Pose
+    } else {
+        Key::Input
+    };
+
+    let keys: Vec<Vec<[f64; 3]>> = runs
+        .iter()
+        .map(|run| run.iter().map(|frame| frame.key(key)).collect())
+        .collect();
+
+    // Pick the run closest to all the others as the reference.
+    let mut totals = vec![0.; runs.len()];
+    for a in 0..runs.len() {
+        for b in a + 1..runs.len() {
+            let (_, cost) = dtw(&keys[a], &keys[b]);
+            totals[a] += cost;
+            totals[b] += cost;
+        }
+    }
+    let reference = (0..runs.len())
+        .min_by(|&a, &b| totals[a].total_cmp(&totals[b]))
+        .unwrap_or(0);
+
+    // For each reference frame, the frames of every run aligned to it.
+    let len = runs[reference].len();
+    let mut matches: Vec<Vec<Vec<Frame>>> = vec![vec![Vec::new(); runs.len()]; len];
+    for (run, frames) in runs.iter().enumerate() {
+        let (path, _) = dtw(&keys[reference], &keys[run]);
+
+        for (i, j) in path {
+            matches[i][run].push(frames[j]);
+        }
+    }
+
+    let mut averaged = Vec::with_capacity(len);
+    let mut deviations = Vec::with_capacity(len);
+
+    for (i, matched) in matches.iter().enumerate() {
+        // One representative frame per run: the middle of whatever was aligned to this frame.
+        let frames: Vec<Frame> = matched
+            .iter()
+            .map(|frames| frames[frames.len() / 2])
+            .collect();
+        let count = frames.len() as f64;
+
+        let time = matched
+            .iter()
+            .map(|frames| {
+                frames.iter().map(|frame| frame.time as f64).sum::<f64>() / frames.len() as f64
+            })
+            .sum::<f64>()
+            / count;
+
+        let mut input = [0.; 3];
+        for frame in &frames {
+            for (sum, value) in input.iter_mut().zip(frame.input) {
+                *sum += value / count;
+            }
+        }
+        let input = input.map(|value| (value * STICK_STEPS).round() / STICK_STEPS);
+
+        // Headings are unwrapped around the reference before averaging so they don't cancel out
+        // across +-PI.
+        let poses: Vec<[f64; 3]> = frames.iter().filter_map(|frame| frame.pose).collect();
+        let pose = runs[reference][i].pose.map(|[_, _, h0]| {
+            let n = poses.len() as f64;
+            poses.iter().fold([0.; 3], |[x, y, h], pose| {
+                [
+                    x + pose[0] / n,
+                    y + pose[1] / n,
+                    h + (h0 + wrap(pose[2] - h0)) / n,
+                ]
+            })
+        });
+
+        let batteries: Vec<f64> = frames.iter().filter_map(|frame| frame.battery).collect();
+        let battery =
+            (!batteries.is_empty()).then(|| batteries.iter().sum::<f64>() / batteries.len() as f64);
+
+        let intake: Vec<Command> = frames.iter().map(|frame| frame.intake).collect();
+        let router: Vec<Command> = frames.iter().map(|frame| frame.router).collect();
+
+        let frame = Frame {
+            time: time.round() as u64,
+            input,
+            pose: pose.map(|[x, y, h]| [x, y, wrap(h)]),
+            battery,
+            intake: vote(&intake),
+            router: vote(&router),
+        };
+
+        let square = |value: f64| value * value;
+        deviations.push((
+            frame.time,
+            pose.map(|[x, y, _]| {
+                poses
+                    .iter()
+                    .map(|p| square(p[0] - x) + square(p[1] - y))
+                    .sum::<f64>()
+                    / poses.len() as f64
+            }),
+            pose.map(|[_, _, h]| {
+                poses.iter().map(|p| square(wrap(p[2] - h))).sum::<f64>() / poses.len() as f64
+            }),
+            frames
+                .iter()
+                .map(|f| distance(f.input, frame.input).powi(2))
+                .sum::<f64>()
+                / count,
+        ));
+
+        averaged.push(frame);
+    }
+
+    // Averaged times can step backwards where runs disagree; hold them instead.
+    for i in 1..averaged.len() {
+        averaged[i].time = averaged[i].time.max(averaged[i - 1].time);
+    }
+
+    Average {
+        events: to_events(&averaged),
+        key,
+        reference,
+        spread: spread(&deviations),
+    }
+}
+
+/// Groups per-frame squared deviations into segments of RMS spread.
+fn spread(deviations: &[(u64, Option<f64>, Option<f64>, f64)]) -> Vec<Spread> {
+    let end = deviations.last().map_or(0, |&(time, ..)| time);
+    let mut segments = Vec::new();
+    let mut from = 0;
+
+    while from <= end {
+        let to = from + SEGMENT;
+        let frames: Vec<_> = deviations
+            .iter()
+            .filter(|&&(time, ..)| (from..to).contains(&time))
+            .collect();
+
+        if !frames.is_empty() {
+            let rms = |values: Vec<f64>| {
+                (!values.is_empty())
+                    .then(|| (values.iter().sum::<f64>() / values.len() as f64).sqrt())
+            };
+
+            segments.push(Spread {
+                from,
+                to: to.min(end),
+                position: rms(frames.iter().filter_map(|frame| frame.1).collect()),
+                heading: rms(frames.iter().filter_map(|frame| frame.2).collect()),
+                input: rms(frames.iter().map(|frame| frame.3).collect()).unwrap_or_default(),
+            });
+        }
+
+        from = to;
+    }
+
+    segments
+}
+
+fn command_event(mechanism: Mechanism, command: Command) -> Event {
+    match (mechanism, command) {
+        (Mechanism::Intake, Command::Forward) => Event::IntakeFwd,
+        (Mechanism::Intake, Command::Reverse) => Event::IntakeRev,
+        (Mechanism::Intake, Command::Disabled) => Event::IntakeDisable,
+        (Mechanism::Router, Command::Forward) => Event::RouterFwd,
+        (Mechanism::Router, Command::Reverse) => Event::RouterRev,
+        (Mechanism::Router, Command::Disabled) => Event::RouterDisable,
+        (mechanism, Command::Voltage(volts)) => Event::SetVoltage(mechanism, volts),
+    }
+}
+
+/// Writes frames back out as a recording, with an event only where something changed.
+fn to_events(frames: &[Frame]) -> Vec<Event> {
+    let mut timeline = Vec::new();
+    let mut previous: Option<Frame> = None;
+    let mut battery = None;
+
+    for frame in frames {
+        let mut push = |event| {
+            timeline.push(edit::Timed {
+                time: frame.time,
+                event,
+            })
+        };
+
+        if let Some(volts) = frame.battery
+            && battery.is_none_or(|battery: f64| (battery - volts).abs() >= BATTERY_RESOLUTION)
+        {
+            push(Event::Battery(volts));
+            battery = Some(volts);
+        }
+
+        if let Some([x, y, h]) = frame.pose
+            && previous.is_none_or(|previous| previous.pose != frame.pose)
+        {
+            push(Event::Pose { x, y, h });
+        }
+
+        if previous.is_none_or(|previous| previous.input != frame.input) {
+            let [x, y, r] = frame.input;
+            push(Event::Input { x, y, r });
+        }
+
+        if previous.is_none_or(|previous| previous.intake != frame.intake) {
+            push(command_event(Mechanism::Intake, frame.intake));
+        }
+
+        if previous.is_none_or(|previous| previous.router != frame.router) {
+            push(command_event(Mechanism::Router, frame.router));
+        }
+
+        previous = Some(*frame);
+    }
+
+    let end = frames.last().map_or(0, |frame| frame.time);
+    edit::from_timeline(&timeline, end)
+}
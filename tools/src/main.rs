@@ -0,0 +1,599 @@
+//! Host-side tool for working with auton recordings.
+
+use std::{env, fmt::Write, fs, process::ExitCode};
+
+use replay::{
+    Event, Mechanism, compact, edit,
+    format::{self, DecodeError},
+    path::{self, Step},
+    validate::{self, AUTONOMOUS_PERIOD, Issue},
+};
+
+mod average;
+
+const USAGE: &str = "\
+usage: replay-tool <command> [args]
+
+Files ending in `.rs` are read and written as a generated `src/auton.rs`. Anything else is read
+as a `.pbr` recording for the SD card in either format, and written in the compact format. Times
+are in seconds.
+
+commands:
+  path <in> <out> [tolerance]
+      Simplify a recording's poses into a path for motion control. `tolerance` is how far, in
+      meters, the path may stray from the recording (default 0.05).
+  trim <in> <out>
+      Remove dead time before the robot starts moving and after it stops.
+  splice <out> <in>[@from..to]...
+      Play segments of recordings back to back.
+  scale <in> <out> <from> <to> <factor>
+      Stretch the segment between `from` and `to` by `factor` (above 1 is slower).
+  insert <in> <out> <time> <event>
+      Insert a mechanism event: intake-fwd, intake-rev, intake-off, router-fwd, router-rev,
+      router-off, or <intake|router>=<volts>.
+  export <in> <out>
+      Convert between `.pbr` and `.rs`.
+  average <out> <in> <in>...
+      Time-align several runs of the same route and average them into one recording, reporting
+      how much the runs disagree over each second.
+  roundtrip <in>...
+      Check that recordings survive encoding and decoding in both formats, and compare sizes.
+  validate <in>...
+      Check that recordings fit in the 15 second autonomous period and end with the robot
+      stopped.";
+
+/// Parses the `FRAMES` array out of a generated `auton.rs`.
+fn from_source(source: &str) -> Result<Vec<Event>, String> {
+    let source: String = source.chars().filter(|c| !c.is_whitespace()).collect();
+    let (_, frames) = source
+        .split_once("=[")
+        .ok_or("couldn't find the FRAMES array")?;
+    let frames = frames.trim_end_matches("];");
+
+    let fields = |body: &str, names: [&str; 3]| -> Result<[f64; 3], String> {
+        let body = body
+            .strip_prefix('{')
+            .and_then(|body| body.strip_suffix('}'))
+            .ok_or_else(|| format!("invalid fields: {body}"))?;
+        let mut values = [0.; 3];
+
+        for field in body.split(',').filter(|field| !field.is_empty()) {
+            let (name, value) = field
+                .split_once(':')
+                .ok_or_else(|| format!("invalid field: {field}"))?;
+            let index = names
+                .iter()
+                .position(|n| *n == name)
+                .ok_or_else(|| format!("unknown field: {name}"))?;
+
+            values[index] = parse(value, "number")?;
+        }
+
+        Ok(values)
+    };
+
+    frames
+        .split("Event::")
+        .map(|frame| frame.trim_end_matches(','))
+        .filter(|frame| !frame.is_empty())
+        .map(|frame| {
+            Ok(match frame {
+                "IntakeFwd" => Event::IntakeFwd,
+                "IntakeRev" => Event::IntakeRev,
+                "IntakeDisable" => Event::IntakeDisable,
+                "RouterFwd" => Event::RouterFwd,
+                "RouterRev" => Event::RouterRev,
+                "RouterDisable" => Event::RouterDisable,
+                _ => {
+                    if let Some(body) = frame.strip_prefix("Input") {
+                        let [x, y, r] = fields(body, ["x", "y", "r"])?;
+                        Event::Input { x, y, r }
+                    } else if let Some(body) = frame.strip_prefix("Pose") {
+                        let [x, y, h] = fields(body, ["x", "y", "h"])?;
+                        Event::Pose { x, y, h }
+                    } else if let Some(volts) = frame
+                        .strip_prefix("Battery(")
+                        .and_then(|rest| rest.strip_suffix(')'))
+                    {
+                        Event::Battery(parse(volts, "voltage")?)
+                    } else if let Some(micros) = frame
+                        .strip_prefix("Wait(")
+                        .and_then(|rest| rest.strip_suffix(')'))
+                    {
+                        Event::Wait(parse(micros, "wait")?)
+                    } else if let Some(args) = frame
+                        .strip_prefix("SetVoltage(replay::Mechanism::")
+                        .and_then(|rest| rest.strip_suffix(')'))
+                    {
+                        let (mechanism, volts) = args
+                            .split_once(',')
+                            .ok_or_else(|| format!("invalid event: {frame}"))?;
+                        let mechanism = match mechanism {
+                            "Intake" => Mechanism::Intake,
+                            "Router" => Mechanism::Router,
+                            _ => return Err(format!("invalid mechanism: {mechanism}")),
+                        };
+
+                        Event::SetVoltage(mechanism, parse(volts, "voltage")?)
+                    } else {
+                        return Err(format!("invalid event: {frame}"));
+                    }
+                }
+            })
+        })
+        .collect()
+}
+
+fn read_recording(path: &str) -> Result<Vec<Event>, String> {
+    if path.ends_with(".rs") {
+        let source =
+            fs::read_to_string(path).map_err(|err| format!("couldn't read {path}: {err}"))?;
+        return from_source(&source).map_err(|err| format!("couldn't parse {path}: {err}"));
+    }
+
+    let bytes = fs::read(path).map_err(|err| format!("couldn't read {path}: {err}"))?;
+
+    decode_bytes(&bytes).map_err(|err| format!("couldn't decode {path}: {err}"))
+}
+
+fn write_recording(path: &str, events: &[Event]) -> Result<(), String> {
+    let bytes = if path.ends_with(".rs") {
+        to_source(events).into_bytes()
+    } else {
+        compact::encode(events)
+    };
+
+    fs::write(path, bytes).map_err(|err| format!("couldn't write {path}: {err}"))
+}
+
+/// Formats a float the way rustfmt leaves the literals in `auton.rs`.
+fn literal(value: f64) -> String {
+    if value.fract() == 0. && value.abs() < 1e15 {
+        format!("{value}.")
+    } else {
+        format!("{value:?}")
+    }
+}
+
+fn to_source(events: &[Event]) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "// Auton replay").ok();
+    writeln!(out).ok();
+    writeln!(out, "pub use replay::Event;").ok();
+    writeln!(out).ok();
+    writeln!(out, "pub static FRAMES: [Event; {}] = [", events.len()).ok();
+
+    for event in events {
+        match *event {
+            Event::Input { x, y, r } => {
+                writeln!(out, "    Event::Input {{").ok();
+                writeln!(out, "        x: {},", literal(x)).ok();
+                writeln!(out, "        y: {},", literal(y)).ok();
+                writeln!(out, "        r: {},", literal(r)).ok();
+                writeln!(out, "    }},").ok();
+            }
+            Event::Pose { x, y, h } => {
+                writeln!(out, "    Event::Pose {{").ok();
+                writeln!(out, "        x: {},", literal(x)).ok();
+                writeln!(out, "        y: {},", literal(y)).ok();
+                writeln!(out, "        h: {},", literal(h)).ok();
+                writeln!(out, "    }},").ok();
+            }
+            Event::Battery(volts) => {
+                writeln!(out, "    Event::Battery({}),", literal(volts)).ok();
+            }
+            Event::SetVoltage(mechanism, volts) => {
+                writeln!(
+                    out,
+                    "    Event::SetVoltage(replay::Mechanism::{mechanism:?}, {}),",
+                    literal(volts)
+                )
+                .ok();
+            }
+            event => {
+                writeln!(out, "    Event::{event:?},").ok();
+            }
+        }
+    }
+
+    writeln!(out, "];").ok();
+
+    out
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, what: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("invalid {what}: {arg}"))
+}
+
+fn parse_time(arg: &str) -> Result<u64, String> {
+    let seconds: f64 = parse(arg, "time")?;
+
+    if seconds < 0. {
+        return Err(format!("invalid time: {arg}"));
+    }
+
+    Ok((seconds * 1_000_000.).round() as u64)
+}
+
+fn parse_event(arg: &str) -> Result<Event, String> {
+    Ok(match arg {
+        "intake-fwd" => Event::IntakeFwd,
+        "intake-rev" => Event::IntakeRev,
+        "intake-off" => Event::IntakeDisable,
+        "router-fwd" => Event::RouterFwd,
+        "router-rev" => Event::RouterRev,
+        "router-off" => Event::RouterDisable,
+        _ => {
+            let (mechanism, volts) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("invalid event: {arg}"))?;
+
+            let mechanism = match mechanism {
+                "intake" => Mechanism::Intake,
+                "router" => Mechanism::Router,
+                _ => return Err(format!("invalid mechanism: {mechanism}")),
+            };
+
+            Event::SetVoltage(mechanism, parse(volts, "voltage")?)
+        }
+    })
+}
+
+fn cmd_path(args: &[String]) -> Result<(), String> {
+    let [input, output, rest @ ..] = args else {
+        return Err(USAGE.into());
+    };
+
+    let tolerance = match rest {
+        [] => 0.05,
+        [tolerance] => parse(tolerance, "tolerance")?,
+        _ => return Err(USAGE.into()),
+    };
+
+    let events = read_recording(input)?;
+    let trace = path::pose_trace(&events);
+
+    if trace.is_empty() {
+        return Err(format!("{input} has no recorded poses"));
+    }
+
+    let steps = path::to_path(&events, tolerance);
+
+    for step in &steps {
+        match step {
+            Step::MoveTo(waypoint) => println!(
+                "move to ({:.3}, {:.3}) heading {:.1}°",
+                waypoint.x,
+                waypoint.y,
+                waypoint.h.to_degrees()
+            ),
+            Step::Event(event) => println!("  {event:?}"),
+        }
+    }
+
+    let waypoints = steps
+        .iter()
+        .filter(|step| matches!(step, Step::MoveTo(_)))
+        .count();
+    println!("{} poses simplified to {waypoints} waypoints", trace.len());
+
+    write_recording(output, &path::to_events(&steps))
+}
+
+fn cmd_trim(args: &[String]) -> Result<(), String> {
+    let [input, output] = args else {
+        return Err(USAGE.into());
+    };
+
+    write_recording(output, &edit::trim(&read_recording(input)?))
+}
+
+fn cmd_splice(args: &[String]) -> Result<(), String> {
+    let [output, segments @ ..] = args else {
+        return Err(USAGE.into());
+    };
+
+    if segments.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut events = Vec::new();
+
+    for segment in segments {
+        let segment_events = match segment.rsplit_once('@') {
+            Some((input, range)) => {
+                let (from, to) = range
+                    .split_once("..")
+                    .ok_or_else(|| format!("invalid range: {range}"))?;
+                let from = parse_time(from)?;
+                let to = parse_time(to)?;
+
+                if to <= from {
+                    return Err(format!("empty range: {range}"));
+                }
+
+                edit::slice(&read_recording(input)?, from, to)
+            }
+            None => read_recording(segment)?,
+        };
+
+        events = edit::concat(&events, &segment_events);
+    }
+
+    write_recording(output, &events)
+}
+
+fn cmd_scale(args: &[String]) -> Result<(), String> {
+    let [input, output, from, to, factor] = args else {
+        return Err(USAGE.into());
+    };
+
+    let from = parse_time(from)?;
+    let to = parse_time(to)?;
+    let factor: f64 = parse(factor, "factor")?;
+
+    if to <= from || factor <= 0. {
+        return Err(USAGE.into());
+    }
+
+    write_recording(
+        output,
+        &edit::scale(&read_recording(input)?, from, to, factor),
+    )
+}
+
+fn cmd_insert(args: &[String]) -> Result<(), String> {
+    let [input, output, time, event] = args else {
+        return Err(USAGE.into());
+    };
+
+    let event = parse_event(event)?;
+
+    write_recording(
+        output,
+        &edit::insert(&read_recording(input)?, parse_time(time)?, event),
+    )
+}
+
+fn cmd_export(args: &[String]) -> Result<(), String> {
+    let [input, output] = args else {
+        return Err(USAGE.into());
+    };
+
+    write_recording(output, &read_recording(input)?)
+}
+
+fn cmd_average(args: &[String]) -> Result<(), String> {
+    let [output, inputs @ ..] = args else {
+        return Err(USAGE.into());
+    };
+
+    if inputs.len() < 2 {
+        return Err(USAGE.into());
+    }
+
+    let runs = inputs
+        .iter()
+        .map(|input| read_recording(input))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let average = average::average(&runs);
+
+    println!(
+        "aligned {} runs to {} on their {}",
+        runs.len(),
+        inputs[average.reference],
+        match average.key {
+            average::Key::Pose => "poses",
+            average::Key::Input => "stick inputs",
+        }
+    );
+
+    for spread in &average.spread {
+        let mut line = format!(
+            "  {:5.1}s..{:5.1}s  input ±{:.3}",
+            spread.from as f64 / 1e6,
+            spread.to as f64 / 1e6,
+            spread.input
+        );
+
+        if let (Some(position), Some(heading)) = (spread.position, spread.heading) {
+            write!(
+                line,
+                "  position ±{:.3} m  heading ±{:.1}°",
+                position,
+                heading.to_degrees()
+            )
+            .ok();
+        }
+
+        println!("{line}");
+    }
+
+    write_recording(output, &average.events)
+}
+
+fn decode_bytes(bytes: &[u8]) -> Result<Vec<Event>, String> {
+    format::decode(bytes).map_err(|err| match err {
+        DecodeError::Read(never) => match never {},
+        err => format!("{err:?}"),
+    })
+}
+
+fn cmd_roundtrip(args: &[String]) -> Result<(), String> {
+    if args.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut failed = 0;
+
+    for input in args {
+        let events = read_recording(input)?;
+
+        let full = format::encode(&events);
+        let packed = compact::encode(&events);
+
+        // Compare the encoded bytes rather than the events so floats are checked bit for bit.
+        let full_ok = format::encode(&decode_bytes(&full)?) == full;
+        let compact_ok =
+            format::encode(&decode_bytes(&packed)?) == format::encode(&compact::normalize(&events));
+
+        println!(
+            "{input}: {} events, {} bytes, {} bytes compact ({:.1}%)",
+            events.len(),
+            full.len(),
+            packed.len(),
+            packed.len() as f64 / full.len() as f64 * 100.
+        );
+
+        if !full_ok {
+            println!("  full format does not round-trip");
+        }
+
+        if !compact_ok {
+            println!("  compact format does not round-trip");
+        }
+
+        if !full_ok || !compact_ok {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!(
+            "{failed} of {} recordings failed to round-trip",
+            args.len()
+        ));
+    }
+
+    Ok(())
+}
+
+fn cmd_validate(args: &[String]) -> Result<(), String> {
+    if args.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut failed = 0;
+
+    for input in args {
+        let report = validate::validate(&read_recording(input)?, AUTONOMOUS_PERIOD);
+        let state = report.final_state;
+
+        println!("{input}:");
+        println!("  duration: {:.3}s", report.duration as f64 / 1_000_000.);
+        println!(
+            "  final state: drive {:?}, intake {:?}, router {:?}",
+            state.input, state.intake, state.router
+        );
+
+        for issue in &report.issues {
+            match issue {
+                Issue::OutOfRange { time, event } => {
+                    println!("  out of range at {:.3}s: {event:?}", *time as f64 / 1e6)
+                }
+                Issue::AfterPeriod { time, count } => {
+                    println!(
+                        "  {count} events after autonomous ends, starting at {:.3}s",
+                        *time as f64 / 1e6
+                    )
+                }
+                Issue::DriveNotStopped => println!("  drivetrain is not stopped at the end"),
+                Issue::IntakeNotDisabled => println!("  intake is not disabled at the end"),
+                Issue::RouterNotDisabled => println!("  router is not disabled at the end"),
+            }
+        }
+
+        if !report.is_ok() {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!(
+            "{failed} of {} recordings failed validation",
+            args.len()
+        ));
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.split_first() {
+        Some((command, args)) => match command.as_str() {
+            "path" => cmd_path(args),
+            "trim" => cmd_trim(args),
+            "splice" => cmd_splice(args),
+            "scale" => cmd_scale(args),
+            "insert" => cmd_insert(args),
+            "export" => cmd_export(args),
+            "average" => cmd_average(args),
+            "roundtrip" => cmd_roundtrip(args),
+            "validate" => cmd_validate(args),
+            _ => Err(USAGE.into()),
+        },
+        None => Err(USAGE.into()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_round_trips_every_event() {
+        let events = vec![
+            Event::Battery(12.6),
+            Event::Pose {
+                x: -1.25,
+                y: 0.1,
+                h: 3.,
+            },
+            Event::Input {
+                x: 0.047244094488188976,
+                y: -1.,
+                r: 0.,
+            },
+            Event::Wait(28000),
+            Event::IntakeFwd,
+            Event::IntakeRev,
+            Event::IntakeDisable,
+            Event::RouterFwd,
+            Event::RouterRev,
+            Event::RouterDisable,
+            Event::SetVoltage(Mechanism::Intake, 6.5),
+            Event::SetVoltage(Mechanism::Router, -12.),
+        ];
+
+        assert_eq!(from_source(&to_source(&events)), Ok(events));
+    }
+
+    #[test]
+    fn compiled_route_exports_unchanged() {
+        let source = include_str!("../../src/auton.rs");
+
+        assert_eq!(to_source(&from_source(source).unwrap()), source);
+    }
+
+    #[test]
+    fn invalid_events_are_reported() {
+        let source = "pub static FRAMES: [Event; 1] = [Event::Jump];";
+
+        assert_eq!(
+            from_source(source),
+            Err(String::from("invalid event: Jump"))
+        );
+    }
+}